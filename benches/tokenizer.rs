@@ -4,10 +4,10 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use search_parser::tokenizer;
 use search_parser::TokenSpan;
 
-const TEST: &'static str =
+const TEST: &str =
     "(((field.gte:1000)AND data.neq:20)||bla.gte:100.2,tag),test.lte:-10,tag";
 
-const TEST997: &'static str = "(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,aba,bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb)";
+const TEST997: &str = "(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,(aa,aba,bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb),bb)";
 
 fn tokenize(
     expr: &str,
@@ -16,11 +16,18 @@ fn tokenize(
     tokenizer.token_spans().unwrap()
 }
 
+fn scan(
+    expr: &str,
+) -> usize {
+    let mut tokenizer = tokenizer("fsm", expr).unwrap();
+    tokenizer.spans().map(Result::unwrap).count()
+}
+
 fn criterion_benchmark(b: &mut Criterion) {
     let mut c = b.benchmark_group("input-latency-test");
     let c = c.sample_size(500);
-    c.throughput(criterion::Throughput::Bytes(TEST.as_bytes().len() as u64));
-    c.bench_function(&format!("{}-byte input, standard", TEST.len()), |b| {
+    c.throughput(criterion::Throughput::Bytes(TEST.len() as u64));
+    c.bench_function(format!("{}-byte input, standard", TEST.len()), |b| {
         b.iter(|| {
             let res = tokenize(black_box(TEST));
             let _ = black_box(res);
@@ -31,8 +38,8 @@ fn criterion_benchmark(b: &mut Criterion) {
 fn criterion_benchmark_long_str(b: &mut Criterion) {
     let mut c = b.benchmark_group("input-latency-test");
     let c = c.sample_size(500);
-    c.throughput(criterion::Throughput::Bytes(TEST997.as_bytes().len() as u64));
-    c.bench_function(&format!("{}-byte input, standard", TEST997.len()), |b| {
+    c.throughput(criterion::Throughput::Bytes(TEST997.len() as u64));
+    c.bench_function(format!("{}-byte input, standard", TEST997.len()), |b| {
         b.iter(|| {
             let res = tokenize(black_box(TEST997));
             let _ = black_box(res);
@@ -53,13 +60,19 @@ fn criterion_benchmark_exc_long_str(b: &mut Criterion) {
     let data = data.clone() + "," + &data; // 32000
     let data = data.clone() + "," + &data; // 64000
     let data = data.clone() + "," + &data; // 128000
-    c.throughput(criterion::Throughput::Bytes(data.as_bytes().len() as u64));
-    c.bench_function(&format!("{}-byte input, standard", data.len()), |b| {
+    c.throughput(criterion::Throughput::Bytes(data.len() as u64));
+    c.bench_function(format!("{}-byte input, standard", data.len()), |b| {
         b.iter(|| {
             let res = tokenize(black_box(&data));
             let _ = black_box(res);
         })
     });
+    c.bench_function(format!("{}-byte input, streaming", data.len()), |b| {
+        b.iter(|| {
+            let res = scan(black_box(&data));
+            let _ = black_box(res);
+        })
+    });
 }
 
 criterion_group!(
@@ -1,14 +1,16 @@
+use std::net::IpAddr;
+
 use ip_network::IpNetwork;
-use time::{Duration, OffsetDateTime};
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
 
-use crate::{tokens::Token, span::TokenSpan};
+use crate::{errors, tokens::Token, span::{Span, TokenSpan}};
 
 
 pub type Field = String;
 pub type Tag = String;
 pub type TagList = Vec<Tag>;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum Expr {
     /// A property field
     Field(Field),
@@ -16,6 +18,9 @@ pub enum Expr {
     Tag(Tag),
     /// A list of tags (optimization of single tag field)
     Tags(TagList),
+    /// A tag, or the value of the field if given, matching a pattern where
+    /// `*` stands for any number of characters and `?` for a single one
+    Wildcard(Option<Field>, String),
     /// A single-argument operation (such as NOT)
     Apply(ApplyOp, Box<Expr>),
     /// A comparison operation
@@ -50,44 +55,386 @@ impl Default for Expr {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Copy, serde::Serialize, serde::Deserialize)]
+/// The part of the input an expression was parsed from, along with the
+/// spans of its operands in the order [Expr::Apply], [Expr::Combine] and
+/// [Expr::Group] hold them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanTree {
+    pub span: Span,
+    pub children: Vec<SpanTree>,
+}
+
+impl SpanTree {
+    /// The span of an expression without operands
+    pub fn leaf(span: Span) -> Self {
+        Self {
+            span,
+            children: Vec::new(),
+        }
+    }
+    /// The span of an expression covering `span` with the given operands
+    pub fn node(span: Span, children: Vec<SpanTree>) -> Self {
+        Self { span, children }
+    }
+    /// Widens the span to cover `other` as well
+    pub fn join(mut self, other: &Span) -> Self {
+        self.span = self.span.join(other);
+        self
+    }
+}
+
+/// An expression annotated with the span of every node in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub spans: SpanTree,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, spans: SpanTree) -> Self {
+        Self { node, spans }
+    }
+}
+
+#[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[serde(untagged)]
 pub enum Value {
     Integer(i128),
     Float(f64),
     Bool(bool),
     IP(IpNetwork),
+    /// Signed offset from the moment the query is resolved at, see [parse_relative_date]
     RelativeDate(Duration),
-    AbsoluteDate(OffsetDateTime),
+    /// The start of the date and how precisely it was given
+    AbsoluteDate(OffsetDateTime, DatePrecision),
+    /// Unquoted text of a [Token::QUOTED_TERM] or an untyped [Token::UNQUOTED_TERM]
+    String(String),
     Undefined,
 }
 
-impl<'a> From<TokenSpan> for Value {
-    fn from(value: TokenSpan) -> Self {
-        match value.token() {
-            Token::FLOAT => Self::Float(value.str().parse().unwrap()),
-            Token::INTEGER => Self::Integer(value.str().parse().unwrap()),
-            Token::BOOLEAN => Self::Bool(match value.str() {
-                "true" | "yes" => true,
-                "false" | "no" => false,
-                _ => unreachable!(),
-            }),
-            Token::IP_CIDR => todo!(),
-            Token::ABSOLUTE_DATE => todo!(),
-            Token::RELATIVE_DATE => todo!(),
-            _ => unreachable!("lexer fucked up the lexem checking")
+impl std::fmt::Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Integer(v) => f.debug_tuple("Integer").field(v).finish(),
+            Self::Float(v) => f.debug_tuple("Float").field(v).finish(),
+            Self::Bool(v) => f.debug_tuple("Bool").field(v).finish(),
+            Self::IP(v) => f.debug_tuple("IP").field(v).finish(),
+            // the fields of a duration are named differently between versions of `time`
+            Self::RelativeDate(v) => f.debug_tuple("RelativeDate").field(&format_args!("{}s", v.whole_seconds())).finish(),
+            Self::AbsoluteDate(v, precision) => f.debug_tuple("AbsoluteDate").field(v).field(precision).finish(),
+            Self::String(v) => f.debug_tuple("String").field(v).finish(),
+            Self::Undefined => f.write_str("Undefined"),
+        }
+    }
+}
+
+impl TryFrom<TokenSpan> for Value {
+    type Error = errors::Error;
+
+    fn try_from(value: TokenSpan) -> errors::Result<Self> {
+        let invalid = || errors::Error::InvalidValue(value.clone());
+        Ok(match value.token() {
+            Token::FLOAT => Self::Float(value.str().parse().map_err(|_| invalid())?),
+            Token::INTEGER => Self::Integer(value.str().parse().map_err(|_| invalid())?),
+            Token::BOOLEAN => Self::Bool(parse_bool(value.str()).ok_or_else(invalid)?),
+            Token::IP_CIDR => Self::IP(parse_ip_cidr(value.str()).ok_or_else(invalid)?),
+            Token::ABSOLUTE_DATE => match parse_absolute_date(value.str()) {
+                Some((date, precision)) => Self::AbsoluteDate(date, precision),
+                None => return Err(errors::Error::InvalidValue(value)),
+            },
+            Token::RELATIVE_DATE => match parse_relative_date(value.str()) {
+                Some(offset) => Self::RelativeDate(offset),
+                None => return Err(errors::Error::InvalidValue(value)),
+            },
+            Token::QUOTED_TERM => Self::String(unquote(value.str())),
+            Token::UNQUOTED_TERM => parse_unquoted(value.str()),
+            _ => return Err(errors::Error::UnexpectedToken(value)),
+        })
+    }
+}
+
+/// The smallest unit given in an absolute date, `2023-05` has a precision
+/// of a month and covers the entire month
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub enum DatePrecision {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+
+impl DatePrecision {
+    /// Returns the first moment after the span of this precision starting at `start`
+    /// or None if that is beyond the supported range of dates
+    pub fn end_of(self, start: OffsetDateTime) -> Option<OffsetDateTime> {
+        match self {
+            DatePrecision::Year => start.replace_year(start.year() + 1).ok(),
+            DatePrecision::Month if start.month() == Month::December => start
+                .replace_year(start.year() + 1)
+                .ok()?
+                .replace_month(Month::January)
+                .ok(),
+            DatePrecision::Month => start.replace_month(start.month().next()).ok(),
+            DatePrecision::Day => start.checked_add(Duration::DAY),
+            DatePrecision::Hour => start.checked_add(Duration::HOUR),
+            DatePrecision::Minute => start.checked_add(Duration::MINUTE),
+            DatePrecision::Second => start.checked_add(Duration::SECOND),
+        }
+    }
+
+    /// Returns the start of the span of this precision containing `date`
+    pub fn start_of(self, date: OffsetDateTime) -> Option<OffsetDateTime> {
+        let (year, month, day) = date.to_calendar_date();
+        let (hour, minute, second) = date.to_hms();
+        let (month, day, time) = match self {
+            DatePrecision::Year => (Month::January, 1, (0, 0, 0)),
+            DatePrecision::Month => (month, 1, (0, 0, 0)),
+            DatePrecision::Day => (month, day, (0, 0, 0)),
+            DatePrecision::Hour => (month, day, (hour, 0, 0)),
+            DatePrecision::Minute => (month, day, (hour, minute, 0)),
+            DatePrecision::Second => (month, day, (hour, minute, second)),
+        };
+        Some(date
+            .replace_date(Date::from_calendar_date(year, month, day).ok()?)
+            .replace_time(Time::from_hms(time.0, time.1, time.2).ok()?))
+    }
+}
+
+/// Parses an [Token::ABSOLUTE_DATE] of the form `YYYY[-MM[-DD]][(T| )HH[:MM[:SS]]][Z|(+|-)HH[:MM]]`
+///
+/// Missing components are filled with their first value, the offset defaults to UTC.
+/// Returns None if the date does not exist in the calendar.
+pub fn parse_absolute_date(inp: &str) -> Option<(OffsetDateTime, DatePrecision)> {
+    /// Takes two digits after the given separator from the front of the input
+    fn component(inp: &mut &str, sep: &[char]) -> Option<u8> {
+        let rest = inp.strip_prefix(sep)?;
+        let digits = rest.get(..2).filter(|x| x.bytes().all(|b| b.is_ascii_digit()))?;
+        *inp = &rest[2..];
+        digits.parse().ok()
+    }
+
+    // a sign would allow years that cannot be formatted for the search
+    let year: i32 = inp.get(..4).filter(|x| x.bytes().all(|b| b.is_ascii_digit()))?.parse().ok()?;
+    let mut rest = &inp[4..];
+    let mut precision = DatePrecision::Year;
+    let (mut month, mut day, mut hour, mut minute, mut second) = (1, 1, 0, 0, 0);
+    if let Some(m) = component(&mut rest, &['-']) {
+        (month, precision) = (m, DatePrecision::Month);
+        if let Some(d) = component(&mut rest, &['-']) {
+            (day, precision) = (d, DatePrecision::Day);
+        }
+    }
+    if let Some(h) = component(&mut rest, &['T', ' ']) {
+        (hour, precision) = (h, DatePrecision::Hour);
+        if let Some(m) = component(&mut rest, &[':']) {
+            (minute, precision) = (m, DatePrecision::Minute);
+            if let Some(s) = component(&mut rest, &[':']) {
+                (second, precision) = (s, DatePrecision::Second);
+            }
+        }
+    }
+    let offset = match rest {
+        "" | "Z" => UtcOffset::UTC,
+        _ => {
+            let sign = if rest.starts_with('-') { -1 } else { 1 };
+            let hours = component(&mut rest, &['+', '-'])? as i8;
+            let minutes = component(&mut rest, &[':']).unwrap_or(0) as i8;
+            if !rest.is_empty() {
+                return None;
+            }
+            UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()?
+        }
+    };
+    let date = Date::from_calendar_date(year, Month::try_from(month).ok()?, day).ok()?;
+    let time = Time::from_hms(hour, minute, second).ok()?;
+    let start = PrimitiveDateTime::new(date, time).assume_offset(offset);
+    // the end of the span must be a four digit year as well to query for the whole span
+    if precision.end_of(start)?.year() > 9999 {
+        return None;
+    }
+    Some((start, precision))
+}
+
+/// Parses a [Token::RELATIVE_DATE] such as `1 year 2 days ago` into its offset
+/// from now, negative for `ago` and positive for `from now`
+///
+/// Months and years have no fixed length, a month is counted as 30 days and
+/// a year as 365 days. Returns None if the offset overflows.
+pub fn parse_relative_date(inp: &str) -> Option<Duration> {
+    let (amounts, sign) = match inp.strip_suffix("ago") {
+        Some(amounts) => (amounts, -1),
+        None => (inp.strip_suffix("now")?.trim_end().strip_suffix("from")?, 1),
+    };
+    let mut words = amounts.split_whitespace();
+    let mut offset = Duration::ZERO;
+    while let Some(amount) = words.next() {
+        let amount: i32 = amount.parse().ok()?;
+        let unit = match words.next()?.trim_end_matches('s') {
+            "second" => Duration::SECOND,
+            "minute" => Duration::MINUTE,
+            "hour" => Duration::HOUR,
+            "day" => Duration::DAY,
+            "week" => Duration::WEEK,
+            "month" => Duration::days(30),
+            "year" => Duration::days(365),
+            _ => return None,
+        };
+        offset = offset.checked_add(unit.checked_mul(amount)?)?;
+    }
+    offset.checked_mul(sign)
+}
+
+/// Parses a [Token::IP_CIDR] into a network, single addresses become
+/// a network of just that address and host bits of a network are cleared
+pub fn parse_ip_cidr(inp: &str) -> Option<IpNetwork> {
+    if inp.contains('/') {
+        IpNetwork::from_str_truncate(inp).ok()
+    } else {
+        inp.parse::<IpAddr>().ok().map(IpNetwork::from)
+    }
+}
+
+/// Parses a [Token::BOOLEAN] of `true`, `yes`, `false` or `no` regardless of its case
+pub fn parse_bool(inp: &str) -> Option<bool> {
+    match inp.to_ascii_lowercase().as_str() {
+        "true" | "yes" => Some(true),
+        "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Types the text of a [Token::UNQUOTED_TERM] as the first kind of value it
+/// entirely is, in the order integer, float, boolean, IP, absolute and relative
+/// date, and otherwise as a string with its backslash escapes resolved
+pub fn parse_unquoted(inp: &str) -> Value {
+    if let Ok(v) = inp.parse() {
+        return Value::Integer(v);
+    }
+    // rust also parses "inf", "NaN" or "1e5" as floats
+    if inp.bytes().all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'+' | b'-')) {
+        if let Ok(v) = inp.parse() {
+            return Value::Float(v);
         }
     }
+    if let Some(v) = parse_bool(inp) {
+        return Value::Bool(v);
+    }
+    if let Some(v) = parse_ip_cidr(inp) {
+        return Value::IP(v);
+    }
+    if let Some((date, precision)) = parse_absolute_date(inp) {
+        return Value::AbsoluteDate(date, precision);
+    }
+    if let Some(offset) = parse_relative_date(inp) {
+        return Value::RelativeDate(offset);
+    }
+    Value::String(unescape(inp))
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Resolves the backslash escapes of a [Token::UNQUOTED_TERM], a backslash
+/// stands for the character following it
+pub fn unescape(inp: &str) -> String {
+    let mut out = String::with_capacity(inp.len());
+    let mut chars = inp.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '\\' => out.push(chars.next().unwrap_or('\\')),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The text a [Expr::Wildcard] pattern starts with if its only wildcard is a
+/// single trailing `*`, with backslash escapes resolved
+pub fn wildcard_prefix(pattern: &str) -> Option<String> {
+    let mut prefix = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '\\' => prefix.extend(chars.next()),
+            '*' if chars.as_str().is_empty() => return Some(prefix),
+            '*' | '?' => return None,
+            c => prefix.push(c),
+        }
+    }
+    None
+}
+
+/// The tag of a [Token::TAG], with the escapes of `*` and `?` resolved
+///
+/// Other backslashes are part of the tag, such as the one of `\o/`.
+pub fn untag(inp: &str) -> String {
+    let mut out = String::with_capacity(inp.len());
+    let mut chars = inp.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '\\' => match chars.next() {
+                Some(c @ ('*' | '?')) => out.push(c),
+                Some(c) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Strips the surrounding quotes from a [Token::QUOTED_TERM] and resolves
+/// the `\"` and `\\` escapes inside it
+pub fn unquote(inp: &str) -> String {
+    let inner = inp
+        .strip_prefix('"')
+        .and_then(|x| x.strip_suffix('"'))
+        .unwrap_or(inp);
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '\\' => match chars.next() {
+                Some(c @ ('"' | '\\')) => out.push(c),
+                Some(c) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum ApplyOp {
     Not,
-    Fuzz,
-    Boost,
+    /// Edit distance, or the similarity of the old Lucene syntax if below 1
+    Fuzz(f64),
+    /// Factor applied to the relevance score
+    Boost(f64),
+}
+
+impl TryFrom<TokenSpan> for ApplyOp {
+    type Error = errors::Error;
+
+    fn try_from(value: TokenSpan) -> errors::Result<Self> {
+        // the argument follows the single operator character
+        let argument = || value.str().get(1..).and_then(|x| x.parse().ok()).ok_or_else(|| errors::Error::InvalidValue(value.clone()));
+        Ok(match value.token() {
+            Token::NOT => Self::Not,
+            Token::BOOST => Self::Boost(argument()?),
+            Token::FUZZ => Self::Fuzz(argument()?),
+            _ => return Err(errors::Error::UnexpectedToken(value)),
+        })
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum Comp {
     LessThan,
     LessThanOrEqual,
@@ -98,25 +445,115 @@ pub enum Comp {
     Contains,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum CombOp {
     And,
     Or,
 }
 
-impl From<Token> for CombOp {
-    fn from(value: Token) -> Self {
-        match value {
-            Token::AND => Self::And,
-            Token::OR => Self::Or,
-            _ => unreachable!(),
+impl TryFrom<TokenSpan> for CombOp {
+    type Error = errors::Error;
+
+    fn try_from(value: TokenSpan) -> errors::Result<Self> {
+        match value.token() {
+            Token::AND => Ok(Self::And),
+            Token::OR => Ok(Self::Or),
+            _ => Err(errors::Error::UnexpectedToken(value)),
         }
     }
 }
 
 impl PartialEq<Token> for CombOp {
     fn eq(&self, other: &Token) -> bool {
-        let other: CombOp = (*other).into();
-        *self == other
+        matches!((self, other), (CombOp::And, Token::AND) | (CombOp::Or, Token::OR))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use time::Duration;
+
+    use super::{parse_absolute_date, parse_relative_date, parse_unquoted, untag, wildcard_prefix, DatePrecision, Value};
+
+    #[test]
+    pub fn test_absolute_date() {
+        assert_eq!(
+            Some((datetime!(2023-01-01 0:00 UTC), DatePrecision::Year)),
+            parse_absolute_date("2023")
+        );
+        assert_eq!(
+            Some((datetime!(2023-05-01 0:00 UTC), DatePrecision::Month)),
+            parse_absolute_date("2023-05")
+        );
+        assert_eq!(
+            Some((datetime!(2023-05-01 12:00 +2), DatePrecision::Minute)),
+            parse_absolute_date("2023-05-01T12:00+02:00")
+        );
+        assert_eq!(
+            Some((datetime!(2023-05-01 12:00:01 -3:30), DatePrecision::Second)),
+            parse_absolute_date("2023-05-01 12:00:01-03:30")
+        );
+        assert_eq!(None, parse_absolute_date("2023-13"));
+        assert_eq!(None, parse_absolute_date("2023-02-29"));
+        assert_eq!(None, parse_absolute_date("9999"));
+        assert_eq!(None, parse_absolute_date("-100"));
+        assert_eq!(None, parse_absolute_date("+999"));
+    }
+
+    #[test]
+    pub fn test_date_precision_end() {
+        let end = |s| {
+            let (start, precision) = parse_absolute_date(s).unwrap();
+            precision.end_of(start).unwrap()
+        };
+        assert_eq!(datetime!(2024-01-01 0:00 UTC), end("2023"));
+        assert_eq!(datetime!(2024-01-01 0:00 UTC), end("2023-12"));
+        assert_eq!(datetime!(2024-03-01 0:00 UTC), end("2024-02-29"));
+        assert_eq!(datetime!(2023-05-01 13:00 +2), end("2023-05-01T12+02"));
+    }
+
+    #[test]
+    pub fn test_relative_date() {
+        assert_eq!(Some(Duration::days(-3)), parse_relative_date("3 days ago"));
+        assert_eq!(Some(Duration::weeks(2)), parse_relative_date("2 weeks from now"));
+        assert_eq!(Some(Duration::days(395)), parse_relative_date("1 year 1 month from  now"));
+        assert_eq!(Some(-Duration::hours(1) - Duration::SECOND), parse_relative_date("1 hour 1 second ago"));
+        assert_eq!(Some(Duration::ZERO), parse_relative_date("0 minutes ago"));
+        assert_eq!(None, parse_relative_date("99999999999 years ago"));
+        assert_eq!(None, parse_relative_date("3 fortnights ago"));
+    }
+
+    #[test]
+    pub fn test_unquoted_value() {
+        assert_eq!(Value::Integer(-10), parse_unquoted("-10"));
+        assert_eq!(Value::Float(1.5), parse_unquoted("1.5"));
+        assert_eq!(Value::Bool(true), parse_unquoted("true"));
+        assert_eq!(Value::Bool(false), parse_unquoted("No"));
+        assert_eq!(Value::IP("10.0.0.0/8".parse().unwrap()), parse_unquoted("10.0.0.0/8"));
+        assert_eq!(Value::AbsoluteDate(datetime!(2023-05-01 0:00 UTC), DatePrecision::Month), parse_unquoted("2023-05"));
+        assert_eq!(Value::RelativeDate(Duration::days(-3)), parse_unquoted("3 days ago"));
+        assert_eq!(Value::String("inf".to_string()), parse_unquoted("inf"));
+        assert_eq!(Value::String("someone".to_string()), parse_unquoted("someone"));
+        assert_eq!(Value::String("s(x) a\\b".to_string()), parse_unquoted(r"s\(x\)\ a\\b"));
+        assert_eq!(Value::String("trailing\\".to_string()), parse_unquoted("trailing\\"));
+    }
+
+    #[test]
+    pub fn test_untag() {
+        assert_eq!("rose*", untag(r"rose\*"));
+        assert_eq!("?ose", untag(r"\?ose"));
+        assert_eq!(r"\o/ \(x\)", untag(r"\o/ \(x\)"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_wildcard_prefix() {
+        assert_eq!(Some("pony".to_string()), wildcard_prefix("pony*"));
+        assert_eq!(Some("a*b".to_string()), wildcard_prefix(r"a\*b*"));
+        assert_eq!(Some(String::new()), wildcard_prefix("*"));
+        assert_eq!(None, wildcard_prefix("po*ny"));
+        assert_eq!(None, wildcard_prefix("pony?"));
+        assert_eq!(None, wildcard_prefix(r"pony\*"));
+    }
+}
@@ -0,0 +1,282 @@
+//! Suggestions for completing a query at the cursor, offering what the
+//! tokenizer accepts after the part of the query in front of it
+
+use std::{ops::Range, sync::Arc};
+
+use crate::{
+    diagnostics::COMPARATORS,
+    schema::Schema,
+    tokenizers::fsm::{dialect::Dialect, state_machine::StateMachine, FSM, Tokenizer},
+    tokens::Token,
+};
+
+/// The parentheses offered where the tokenizer accepts them, the other
+/// operators are spelled as in the dialect
+const PARENTHESES: &[(&str, Token)] = &[("(", Token::LPAREN), (")", Token::RPAREN)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionKind {
+    Operator,
+    Comparator,
+    Field,
+    Tag,
+}
+
+/// Text to replace the word at the cursor with
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Suggestion {
+    pub text: String,
+    /// The byte range of the query the text replaces
+    pub range: Range<usize>,
+    pub kind: SuggestionKind,
+}
+
+/// The tags known to the search, completing the tag typed at the cursor
+pub trait TagNames {
+    fn tags_with_prefix(&self, prefix: &str) -> Vec<String>;
+}
+
+impl<S: AsRef<str>> TagNames for Vec<S> {
+    fn tags_with_prefix(&self, prefix: &str) -> Vec<String> {
+        with_prefix(self, prefix)
+    }
+}
+
+impl<S: AsRef<str>, const N: usize> TagNames for [S; N] {
+    fn tags_with_prefix(&self, prefix: &str) -> Vec<String> {
+        with_prefix(self, prefix)
+    }
+}
+
+fn with_prefix<S: AsRef<str>>(tags: &[S], prefix: &str) -> Vec<String> {
+    tags.iter()
+        .map(AsRef::as_ref)
+        .filter(|tag| tag.starts_with(prefix))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Completes the query at the cursor without any fields or tags
+pub fn complete(query: &str, cursor: usize) -> Vec<Suggestion> {
+    complete_with(query, cursor, &Schema::empty(), &Dialect::default(), &[] as &[&str; 0])
+}
+
+/// Like [complete] but offers the fields of the schema and the known tags,
+/// with the operators spelled as in the dialect
+pub fn complete_with(query: &str, cursor: usize, schema: &Schema, dialect: &Dialect, tags: &dyn TagNames) -> Vec<Suggestion> {
+    let Some(before) = query.get(..cursor) else {
+        return Vec::new();
+    };
+    let scan = Scan {
+        schema: Arc::new(schema.clone()),
+        dialect: Arc::new(dialect.clone()),
+    };
+    let mut out = Vec::new();
+    let mut extend = |context: &Context| {
+        for suggestion in suggestions(context, &before[context.start..], cursor, &scan, tags) {
+            if !out.contains(&suggestion) {
+                out.push(suggestion);
+            }
+        }
+    };
+    let first = Context::new(before, &scan);
+    extend(&first);
+    let word = &before[first.start..];
+    // the last word of a tag with spaces may be the start of an operator instead
+    if let Some(start) = word.rfind(char::is_whitespace).map(|i| first.start + i + 1) {
+        let context = Context::new(&before[..start], &scan);
+        if context.start == start {
+            extend(&context);
+        }
+    }
+    // a field followed by the start of a comparator is scanned as a tag
+    if let Some(dot) = word.rfind('.').map(|i| i + 1) {
+        if scan.accepts(first.state, &format!("{}eq:x", &word[..dot]), dot, Token::FIELD) {
+            extend(&Context {
+                state: StateMachine::Field,
+                start: first.start + dot,
+                depth: first.depth,
+            });
+        }
+    }
+    out
+}
+
+/// What the query is scanned with
+struct Scan {
+    schema: Arc<Schema>,
+    dialect: Arc<Dialect>,
+}
+
+impl Scan {
+    fn tokenizer(&self, inp: &str) -> Tokenizer {
+        Tokenizer::new(Arc::from(inp), self.schema.clone()).with_dialect(self.dialect.clone())
+    }
+
+    /// Whether the tokenizer in the state scans the first `len` bytes of the probe as the token
+    fn accepts(&self, state: StateMachine, probe: &str, len: usize, token: Token) -> bool {
+        let mut tokenizer = self.tokenizer(probe);
+        tokenizer.state = state;
+        matches!(tokenizer.step(), Ok(Some(t)) if t.token() == token && t.range() == (0..len))
+    }
+}
+
+/// The state the word at the cursor is typed in
+struct Context {
+    state: StateMachine,
+    /// Where the word starts
+    start: usize,
+    /// The number of groups open in front of the word
+    depth: usize,
+}
+
+impl Context {
+    /// Scans the input in front of the cursor up to the word typed at it
+    fn new(before: &str, scan: &Scan) -> Self {
+        let mut tokenizer = scan.tokenizer(before);
+        let mut context = Context {
+            state: tokenizer.state,
+            start: tokenizer.position,
+            depth: 0,
+        };
+        let mut depth = 0usize;
+        loop {
+            let previous = tokenizer.state;
+            match tokenizer.step() {
+                Ok(Some(t)) if t.token() == Token::EOI => break,
+                // a token up to the cursor may still be typed
+                Ok(Some(t)) if t.range().end == before.len() && t.token() != Token::NEWLINE => {
+                    context = Context {
+                        state: previous,
+                        start: t.range().start,
+                        depth,
+                    };
+                }
+                Ok(Some(t)) => {
+                    match t.token() {
+                        Token::LPAREN => depth += 1,
+                        Token::RPAREN => depth = depth.saturating_sub(1),
+                        _ => (),
+                    }
+                    context = Context {
+                        state: tokenizer.state,
+                        start: tokenizer.position,
+                        depth,
+                    };
+                }
+                // the rest could not be scanned and is the word typed so far
+                Ok(None) | Err(_) => break,
+            }
+        }
+        context
+    }
+}
+
+fn suggestions(context: &Context, word: &str, cursor: usize, scan: &Scan, tags: &dyn TagNames) -> Vec<Suggestion> {
+    let state = context.state;
+    let typed = |text: &str| text.to_lowercase().starts_with(&word.to_lowercase());
+    let suggest = |text: String, kind| Suggestion {
+        text,
+        range: context.start..cursor,
+        kind,
+    };
+    let mut out = Vec::new();
+    for comp in COMPARATORS.iter().map(|c| format!("{c}:")) {
+        if typed(&comp) && scan.accepts(state, &comp, comp.len(), Token::RANGE) {
+            out.push(suggest(comp, SuggestionKind::Comparator));
+        }
+    }
+    for field in scan.schema.fields().iter().flat_map(|f| std::iter::once(&f.name).chain(&f.aliases)) {
+        let colon = format!("{field}:");
+        if typed(&colon) && scan.accepts(state, &format!("{colon}x"), field.len(), Token::FIELD) {
+            out.push(suggest(colon, SuggestionKind::Field));
+        }
+        let dot = format!("{field}.");
+        if typed(&dot) && scan.accepts(state, &format!("{dot}eq:x"), dot.len(), Token::FIELD) {
+            out.push(suggest(dot, SuggestionKind::Field));
+        }
+    }
+    let keywords = scan.dialect.keywords();
+    // the first spelling of a keyword that fits what was typed
+    let keywords = keywords.iter().filter_map(|(token, spellings)| {
+        spellings.iter().find(|k| typed(k)).map(|k| (k.as_str(), *token))
+    });
+    for (op, token) in keywords.chain(PARENTHESES.iter().copied()) {
+        // a group can only be closed if one was opened
+        if token == Token::RPAREN && context.depth == 0 {
+            continue;
+        }
+        if typed(op) && scan.accepts(state, &format!("{op} x"), op.len(), token) {
+            out.push(suggest(op.to_string(), SuggestionKind::Operator));
+        }
+    }
+    if !word.is_empty() && state.next_states().contains(&StateMachine::Tag) {
+        for tag in tags.tags_with_prefix(word) {
+            out.push(suggest(tag, SuggestionKind::Tag));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{complete, complete_with, SuggestionKind};
+    use crate::schema::{FieldDef, FieldType, Schema};
+    use crate::tokenizers::fsm::dialect::Dialect;
+
+    fn texts(query: &str) -> Vec<String> {
+        complete(query, query.len()).into_iter().map(|s| s.text).collect()
+    }
+
+    #[test]
+    pub fn test_complete_comparators() {
+        assert_eq!(vec!["lt:", "lte:", "eq:", "neq:", "gt:", "gte:", "has:"], texts("width."));
+        assert_eq!(vec!["gt:", "gte:"], texts("width.g"));
+        let suggestions = complete("cute, width.gt", 14);
+        assert_eq!(vec![12..14, 12..14], suggestions.iter().map(|s| s.range.clone()).collect::<Vec<_>>());
+        assert!(suggestions.iter().all(|s| s.kind == SuggestionKind::Comparator));
+    }
+
+    #[test]
+    pub fn test_complete_operators() {
+        assert_eq!(vec!["AND", "OR", ")"], texts("(cute "));
+        assert_eq!(vec!["AND", "OR"], texts("width.gt:10 "));
+        assert_eq!(vec!["OR"], texts("width.gt:10 O"));
+        assert_eq!(vec!["NOT", "("], texts("cute AND "));
+        assert_eq!(vec!["&&"], texts("cute &"));
+    }
+
+    #[test]
+    pub fn test_complete_dialect_operators() {
+        let dialect = Dialect::default().with_and(&["and", "&"]).with_or(&["or"]).with_not(&["not"]);
+        let texts = |query: &str| {
+            complete_with(query, query.len(), &Schema::empty(), &dialect, &[] as &[&str; 0])
+                .into_iter()
+                .map(|s| s.text)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["and", "or"], texts("cute "));
+        assert_eq!(vec!["not", "("], texts("cute and "));
+        assert_eq!(vec!["&"], texts("cute &"));
+    }
+
+    #[test]
+    pub fn test_complete_fields_and_tags() {
+        let schema = Schema::empty()
+            .with_field(FieldDef::new("width", FieldType::Integer))
+            .with_field(FieldDef::new("uploader", FieldType::Keyword).with_alias("user"));
+        let tags = ["safe", "sunset", "cute"];
+        let suggestions = complete_with("safe || (u", 10, &schema, &Dialect::default(), &tags);
+        assert_eq!(
+            vec![("uploader:", SuggestionKind::Field), ("uploader.", SuggestionKind::Field), ("user:", SuggestionKind::Field), ("user.", SuggestionKind::Field)],
+            suggestions.iter().map(|s| (s.text.as_str(), s.kind)).collect::<Vec<_>>()
+        );
+        assert_eq!(9..10, suggestions[0].range);
+        let suggestions = complete_with("NOT su", 6, &schema, &Dialect::default(), &tags);
+        assert_eq!(vec!["sunset"], suggestions.iter().map(|s| s.text.as_str()).collect::<Vec<_>>());
+        assert_eq!(4..6, suggestions[0].range);
+        let suggestions = complete_with("sunset A", 8, &schema, &Dialect::default(), &tags);
+        assert_eq!(vec![("AND", 7..8)], suggestions.iter().map(|s| (s.text.as_str(), s.range.clone())).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,227 @@
+//! Renders errors against the query they occurred in, as text pointing at
+//! the failing part of the query or as JSON for frontends
+
+use std::ops::Range;
+
+use crate::{errors::Error, schema::Schema, span::TokenSpan, tokens::Token};
+
+/// The comparators that can follow a field, used to suggest a fix for typos
+pub(crate) const COMPARATORS: &[&str] = &["lt", "lte", "eq", "neq", "gt", "gte", "has"];
+
+/// An error with the byte range of the query it refers to and a hint on how to fix it
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Diagnostic {
+    /// Stable identifier of the kind of error
+    pub code: &'static str,
+    pub message: String,
+    /// Missing if the error does not point at a part of the query
+    pub range: Option<Range<usize>>,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    /// Describes the error, using the schema to suggest fields for misspelled ones
+    pub fn new(error: &Error, schema: &Schema) -> Self {
+        let span = |t: &TokenSpan| Some(t.range());
+        let (range, message, hint) = match error {
+            Error::ExpectedDifferentTokens(expected, got) => {
+                let mut expected = expected.clone();
+                expected.sort();
+                expected.dedup();
+                let wants_comparator = expected.contains(&Token::RANGE);
+                let expected = itertools::join(expected.iter().map(|t| t.name()), ", ");
+                let message = format!("expected one of {expected} but found {}", found(got));
+                match got.str().split_once(':') {
+                    // a misspelled comparator after a field such as `width.gtee:`
+                    Some((word, _)) if wants_comparator => (
+                        Some(got.range().start..got.range().start + word.len() + 1),
+                        message,
+                        closest(word, COMPARATORS.iter().copied()).map(|c| format!("did you mean `{c}:`?")),
+                    ),
+                    _ => (span(got), message, None),
+                }
+            }
+            Error::UnexpectedToken(t) => {
+                let hint = match t.token() {
+                    Token::LPAREN => Some("this `(` is never closed".to_string()),
+                    Token::RPAREN => Some("this `)` has no matching `(`".to_string()),
+                    Token::EOI => Some("the query ends before it is complete".to_string()),
+                    _ => None,
+                };
+                (span(t), format!("unexpected {}", found(t)), hint)
+            }
+            Error::UnknownField(t) => {
+                let name = t.str().trim_end_matches(['.', ':']);
+                let names = schema
+                    .fields()
+                    .iter()
+                    .flat_map(|f| std::iter::once(&f.name).chain(&f.aliases))
+                    .map(String::as_str);
+                (
+                    Some(t.range().start..t.range().start + name.len()),
+                    format!("unknown field {name:?}"),
+                    closest(name, names).map(|f| format!("did you mean `{f}`?")),
+                )
+            }
+            Error::TypeMismatch(ty, t) => (
+                span(t),
+                format!("expected {} value but found {}", ty.name(), found(t)),
+                example(*ty).map(|e| format!("{} values look like {e}", ty.name())),
+            ),
+            Error::InvalidValue(t) | Error::LexemeTooLong(t, _) | Error::NestedTooDeep(t) => (span(t), error.to_string(), None),
+            error => (None, error.to_string(), None),
+        };
+        Self {
+            code: code(error),
+            message,
+            range,
+            hint,
+        }
+    }
+
+    /// Renders the diagnostic as text, underlining the failing part of the query
+    pub fn render(&self, query: &str) -> String {
+        let mut out = format!("error[{}]: {}\n", self.code, self.message);
+        if let Some(range) = self.range.clone().filter(|r| r.end <= query.len()) {
+            let line_start = query[..range.start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = query[range.start..].find('\n').map_or(query.len(), |i| range.start + i);
+            let line_no = query[..line_start].matches('\n').count() + 1;
+            let column = query[line_start..range.start].chars().count();
+            let width = query[range.start..range.end.min(line_end)].chars().count().max(1);
+            let gutter = " ".repeat(line_no.to_string().len());
+            out += &format!("{gutter} |\n");
+            out += &format!("{line_no} | {}\n", &query[line_start..line_end]);
+            out += &format!("{gutter} | {}{}\n", " ".repeat(column), "^".repeat(width));
+        }
+        if let Some(hint) = &self.hint {
+            out += &format!("  = hint: {hint}\n");
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+}
+
+fn code(error: &Error) -> &'static str {
+    match error {
+        Error::ExpectedTokensNotFound(_) => "expected_tokens_not_found",
+        Error::ExpectedDifferentTokens(..) => "expected_different_tokens",
+        Error::InvalidValue(_) => "invalid_value",
+        Error::UnexpectedToken(_) => "unexpected_token",
+        Error::LexemeTooLong(..) => "lexeme_too_long",
+        Error::NestedTooDeep(_) => "nested_too_deep",
+        Error::UnknownField(_) => "unknown_field",
+        Error::TypeMismatch(..) => "type_mismatch",
+        Error::InvalidSchema(..) => "invalid_schema",
+        Error::DateOutOfRange(_) => "date_out_of_range",
+        Error::UnsupportedFuzz => "unsupported_fuzz",
+        Error::IntegerOutOfRange(_) => "integer_out_of_range",
+        Error::UnsupportedExpr(_) => "unsupported_expr",
+        Error::ParseIntError(_) => "parse_int_error",
+        Error::UnknownTokenizer(_) => "unknown_tokenizer",
+        Error::UnknownParser(_) => "unknown_parser",
+        Error::UnknownTransformer(_) => "unknown_transformer",
+        Error::SerdeJson(_) => "serde_json",
+        Error::IOError(_) => "io_error",
+    }
+}
+
+/// The token as it appeared in the query
+fn found(t: &TokenSpan) -> String {
+    match t.str() {
+        "" => "end of input".to_string(),
+        s => format!("{s:?}"),
+    }
+}
+
+fn example(ty: crate::schema::FieldType) -> Option<&'static str> {
+    use crate::schema::FieldType;
+    match ty {
+        FieldType::Integer => Some("`10`"),
+        FieldType::Float => Some("`1.5`"),
+        FieldType::Date => Some("`2023-01-31` or `3 days ago`"),
+        FieldType::Ip => Some("`10.0.0.1` or `10.0.0.0/8`"),
+        FieldType::Bool => Some("`true` or `false`"),
+        FieldType::Keyword | FieldType::Text => None,
+    }
+}
+
+/// The candidate closest to the word if it is close enough to be a typo of it
+fn closest<'a>(word: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let word = word.to_lowercase();
+    candidates
+        .map(|c| (distance(&word, c), c))
+        .filter(|(d, c)| *d > 0 && *d <= 2 && *d < c.chars().count())
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+/// Edit distance counting swapped neighbours as a single edit
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::Diagnostic;
+    use crate::Schema;
+
+    fn diagnose(query: &str) -> Diagnostic {
+        let error = crate::tokenizer("fsm", query)
+            .and_then(|t| crate::parser("shift_reduce", t))
+            .and_then(|mut p| p.produce_tree())
+            .unwrap_err();
+        Diagnostic::new(&error, &Schema::default())
+    }
+
+    #[test]
+    pub fn test_comparator_hint() {
+        let diag = diagnose("width.gtee:10");
+        assert_eq!(Some(6..11), diag.range);
+        assert_eq!(Some("did you mean `gte:`?"), diag.hint.as_deref());
+        assert_eq!(
+            "error[expected_different_tokens]: expected one of Range but found \"gtee:10\"\n  |\n1 | width.gtee:10\n  |       ^^^^^\n  = hint: did you mean `gte:`?\n",
+            diag.render("width.gtee:10")
+        );
+    }
+
+    #[test]
+    pub fn test_field_hint() {
+        let diag = diagnose("cute, widht.gte:10");
+        assert_eq!("unknown_field", diag.code);
+        assert_eq!(Some(6..11), diag.range);
+        assert_eq!(Some("did you mean `width`?"), diag.hint.as_deref());
+        assert_eq!(
+            r#"{"code":"unknown_field","message":"unknown field \"widht\"","range":{"start":6,"end":11},"hint":"did you mean `width`?"}"#,
+            diag.to_json().unwrap()
+        );
+    }
+
+    #[test]
+    pub fn test_render_end_of_input() {
+        let diag = diagnose("cute AND");
+        assert_eq!(Some(8..8), diag.range);
+        assert_eq!(None, diag.hint);
+        assert!(diag.render("cute AND").ends_with("1 | cute AND\n  |         ^\n"));
+    }
+}
@@ -8,6 +8,28 @@ pub enum Error {
     ExpectedTokensNotFound(Vec<Token>),
     #[error("Expected {} but got {}", itertools::join(_0.iter().map(|x| x.name()), ", "), _1.token().name())]
     ExpectedDifferentTokens(Vec<Token>, TokenSpan),
+    #[error("Invalid {}: {:?}", _0.token().name(), _0.str())]
+    InvalidValue(TokenSpan),
+    #[error("Unexpected {} {:?}", _0.token().name(), _0.str())]
+    UnexpectedToken(TokenSpan),
+    #[error("{} is longer than the maximum of {1} bytes", _0.token().name())]
+    LexemeTooLong(TokenSpan, usize),
+    #[error("{} is nested deeper than the maximum of {} levels", _0.token().name(), crate::parsers::MAX_DEPTH)]
+    NestedTooDeep(TokenSpan),
+    #[error("unknown field {:?}", _0.str())]
+    UnknownField(TokenSpan),
+    #[error("expected {} value but got {:?}", _0.name(), _1.str())]
+    TypeMismatch(crate::schema::FieldType, TokenSpan),
+    #[error("invalid schema in line {0}: {1}")]
+    InvalidSchema(usize, String),
+    #[error("{0} is outside of the supported range of dates")]
+    DateOutOfRange(String),
+    #[error("fuzzing is only supported on tags and numeric equality")]
+    UnsupportedFuzz,
+    #[error("integer {0} is too large to search for")]
+    IntegerOutOfRange(i128),
+    #[error("cannot search for {0:?}")]
+    UnsupportedExpr(crate::ast::Expr),
     #[error("Could not parse integer: {0:?}")]
     ParseIntError(#[from] std::num::ParseIntError),
     #[error("unknown tokenizer {0:?}, available tokenizers: {}", crate::tokenizers().join(", "))]
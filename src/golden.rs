@@ -0,0 +1,153 @@
+//! Runs the queries of `samples/` through every tokenizer, parser and
+//! transformer and compares the results with the ones recorded next to them
+//!
+//! `BLESS=1 cargo test golden` records the current results instead, after
+//! checking that every combination agrees on them.
+
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use crate::{errors, Diagnostic, Schema};
+
+/// A query along with what it is expected to parse and transform into
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct Sample {
+    pub query: String,
+    /// The serialized tree of a query that parses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ast: Option<serde_json::Value>,
+    /// The [Diagnostic::code] of a query that does not parse
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The output of every transformer by its name, JSON output is kept as
+    /// is and the diagnostic rendered for errors
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub outputs: BTreeMap<String, serde_json::Value>,
+}
+
+fn path(file: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "samples", file].iter().collect()
+}
+
+/// The samples of the JSON file in `samples/`
+pub(crate) fn samples(file: &str) -> Vec<Sample> {
+    let json = std::fs::read_to_string(path(file)).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+/// The lines of the text file in `samples/`
+fn lines(file: &str) -> Vec<String> {
+    let text = std::fs::read_to_string(path(file)).unwrap();
+    text.lines().filter(|l| !l.is_empty()).map(str::to_string).collect()
+}
+
+/// Collects the output of a transformer
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The query parsed and transformed with the tokenizer and parser
+fn run(query: &str, tok: &str, par: &str) -> Sample {
+    let now = time::macros::datetime!(2024-01-10 12:00 UTC);
+    let schema = Schema::default();
+    let code = |e: &errors::Error| Diagnostic::new(e, &schema).code.to_string();
+    let tree = crate::tokenizer(tok, query)
+        .and_then(|t| crate::parser(par, t))
+        .and_then(|mut p| p.produce_tree());
+    let (ast, error) = match tree {
+        Ok(tree) => (Some(serde_json::to_value(tree).unwrap()), None),
+        Err(e) => (None, Some(code(&e))),
+    };
+    let mut outputs = BTreeMap::new();
+    for tra in crate::transformers() {
+        let output = Output::default();
+        let result = crate::tokenizer(tok, query)
+            .and_then(|t| crate::parser(par, t))
+            .and_then(|p| crate::transformer_with_clock(&tra, p, &now))
+            .and_then(|mut t| t.run(Box::new(output.clone())));
+        let out = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let out = match result {
+            Ok(()) => serde_json::from_str(&out).unwrap_or(serde_json::Value::String(out)),
+            Err(e) => serde_json::Value::String(Diagnostic::new(&e, &schema).render(query)),
+        };
+        outputs.insert(tra, out);
+    }
+    Sample {
+        query: query.to_string(),
+        ast,
+        error,
+        outputs,
+    }
+}
+
+/// Checks the samples of the file against every tokenizer and parser, or
+/// records their results if blessed
+fn check(file: &str) {
+    let bless = std::env::var_os("BLESS").is_some();
+    let mut failures = Vec::new();
+    let mut blessed = Vec::new();
+    for sample in samples(file) {
+        let mut first: Option<Sample> = None;
+        for tok in crate::tokenizers() {
+            for par in crate::parsers() {
+                let actual = run(&sample.query, &tok, &par);
+                let expected = match &first {
+                    Some(first) if bless => first,
+                    None if bless => {
+                        first = Some(actual);
+                        continue;
+                    }
+                    _ => &sample,
+                };
+                if actual != *expected {
+                    let expected = serde_json::to_string_pretty(expected).unwrap();
+                    let actual = serde_json::to_string_pretty(&actual).unwrap();
+                    failures.push(format!("{tok}/{par}: {:?}\nexpected {expected}\nfound {actual}", sample.query));
+                }
+            }
+        }
+        blessed.extend(first);
+    }
+    assert!(failures.is_empty(), "{file}:\n{}", failures.join("\n\n"));
+    if bless {
+        std::fs::write(path(file), serde_json::to_string_pretty(&blessed).unwrap() + "\n").unwrap();
+    }
+}
+
+#[test]
+pub fn test_horrors() {
+    check("horrors.json");
+}
+
+#[test]
+pub fn test_ast_opt() {
+    check("ast_opt.json");
+}
+
+/// Every tag parses into itself once quoted
+#[test]
+pub fn test_tags() {
+    for tag in lines("db_tags.txt") {
+        let query = format!("\"{}\"", tag.replace('\\', "\\\\").replace('"', "\\\""));
+        for tok in crate::tokenizers() {
+            for par in crate::parsers() {
+                let tree = crate::tokenizer(&tok, &query)
+                    .and_then(|t| crate::parser(&par, t))
+                    .and_then(|mut p| p.produce_tree())
+                    .map(|tree| serde_json::to_value(tree).unwrap());
+                assert_eq!(serde_json::json!({ "Tag": tag }), tree.unwrap(), "{tok}/{par}: {query}");
+            }
+        }
+    }
+}
@@ -2,6 +2,8 @@ mod rbm;
 mod quickmap;
 mod tib;
 
+pub use quickmap::QuickMap;
+
 /// TEOs are operations on the index that can be executed in a massively parallel fashion
 /// by allowing each bitmap index to operate independently
 /// 
@@ -39,17 +39,11 @@ impl<const U: u32> QuickIntMap<U> {
 
     fn alloc_free_id(&self) -> Option<u32> {
         let poss_free = (0..Self::MAX_ID).into_iter();
-        let mut values: HashSet<u32> = self.store.values()
+        let values: HashSet<u32> = self.store.values()
             .filter_map(Entry::as_option)
             .collect();
-        let result = poss_free.filter(|x| {
-            if values.contains(x) {
-                values.remove(x);
-                true
-            } else {
-                false
-            }
-        }).next()?;
+        // the first ID no entry holds
+        let result = poss_free.filter(|x| !values.contains(x)).next()?;
         if result < Self::MAX_ID {
             return Some(result)
         }
@@ -126,23 +120,15 @@ impl<const U: u32> QuickMap<U> {
 
     pub fn new() -> Self {
         Self {
-            store: GenericPatriciaMap::new(),
+            store: patricia_tree::GenericPatriciaMap::new(),
         }
     }
 
     fn alloc_free_id(&self) -> Option<u32> {
         let poss_free = (0..Self::MAX_ID).into_iter();
-        let mut values: HashSet<u32> = self.store.values().filter_map(Entry::as_option).collect();
-        let result = poss_free
-            .filter(|x| {
-                if values.contains(x) {
-                    values.remove(x);
-                    true
-                } else {
-                    false
-                }
-            })
-            .next()?;
+        let values: HashSet<u32> = self.store.values().filter_map(Entry::as_option).collect();
+        // the first ID no entry holds
+        let result = poss_free.filter(|x| !values.contains(x)).next()?;
         if result < Self::MAX_ID {
             return Some(result);
         }
@@ -225,3 +211,33 @@ impl<const U: u32> QuickMap<U> {
         }
     }
 }
+
+/// Offers the allocated tags for completion
+impl<const U: u32> crate::completion::TagNames for QuickMap<U> {
+    fn tags_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.store
+            .iter_prefix(prefix)
+            .filter(|(_, entry)| matches!(entry, Entry::Allocated { .. }))
+            .map(|(tag, _)| tag)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::QuickMap;
+    use crate::completion::TagNames;
+
+    #[test]
+    pub fn test_tags_with_prefix() {
+        let mut map = QuickMap::<8>::new();
+        for tag in ["safe", "sunset", "cute", "sun"] {
+            assert!(map.allocate(tag).is_some());
+        }
+        assert_eq!(Some(0), map.resolve("safe"));
+        map.tombstone("sunset");
+        assert_eq!(vec!["safe", "sun"], map.tags_with_prefix("s"));
+        let suggestions = crate::complete_with("NOT su", 6, &Default::default(), &Default::default(), &map);
+        assert_eq!(vec!["sun"], suggestions.iter().map(|s| s.text.as_str()).collect::<Vec<_>>());
+    }
+}
@@ -8,7 +8,7 @@ pub struct MappedTagObjectRelation<const T: u32, const O: u32> {
     obj_map: QuickIntMap<32>,
 }
 
-impl<const T: usize, const O: usize> MappedTagObjectRelation<T, O> {
+impl<const T: u32, const O: u32> MappedTagObjectRelation<T, O> {
     /// Executes a number of TOR expression operators and returns all objects
     /// that are still present at the end of the operation
     fn execute_teo_obj(&self, teos: &[TORExprOperator]) -> Option<Vec<u32>> {
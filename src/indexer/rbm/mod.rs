@@ -2,17 +2,17 @@ mod map;
 
 #[derive(Clone, PartialEq, Debug)]
 #[repr(transparent)]
-struct TagObjectRelation<const T: usize, const O: usize> {
+struct TagObjectRelation<const T: u32, const O: u32> {
     bitmap: roaring::RoaringBitmap,
 }
 
-impl<const T: usize, const O: usize> Default for TagObjectRelation<T, O> {
+impl<const T: u32, const O: u32> Default for TagObjectRelation<T, O> {
     fn default() -> Self {
         Self::empty()
     }
 }
 
-impl<const T: usize, const O: usize> std::ops::BitAnd<Self> for TagObjectRelation<T, O> {
+impl<const T: u32, const O: u32> std::ops::BitAnd<Self> for TagObjectRelation<T, O> {
     type Output = Self;
 
     fn bitand(self, rhs: TagObjectRelation<T, O>) -> Self::Output {
@@ -22,7 +22,7 @@ impl<const T: usize, const O: usize> std::ops::BitAnd<Self> for TagObjectRelatio
     }
 }
 
-impl<const T: usize, const O:usize> std::ops::Not for TagObjectRelation<T, O> {
+impl<const T: u32, const O: u32> std::ops::Not for TagObjectRelation<T, O> {
     type Output = Self;
 
     fn not(self) -> Self::Output {
@@ -30,7 +30,7 @@ impl<const T: usize, const O:usize> std::ops::Not for TagObjectRelation<T, O> {
     }
 }
 
-impl<const T: usize, const O: usize> std::ops::BitOr for TagObjectRelation<T, O> {
+impl<const T: u32, const O: u32> std::ops::BitOr for TagObjectRelation<T, O> {
     type Output = Self;
 
     fn bitor(self, rhs: Self) -> Self::Output {
@@ -40,7 +40,7 @@ impl<const T: usize, const O: usize> std::ops::BitOr for TagObjectRelation<T, O>
     }
 }
 
-impl<const T: usize, const O: usize> std::ops::BitXor for TagObjectRelation<T, O> {
+impl<const T: u32, const O: u32> std::ops::BitXor for TagObjectRelation<T, O> {
     type Output = Self;
 
     fn bitxor(self, rhs: Self) -> Self::Output {
@@ -50,7 +50,7 @@ impl<const T: usize, const O: usize> std::ops::BitXor for TagObjectRelation<T, O
     }
 }
 
-impl<const T: usize, const O: usize> TagObjectRelation<T, O> {
+impl<const T: u32, const O: u32> TagObjectRelation<T, O> {
     // check we use no more than 32 bits
     const _NO_MORE_THAN_32_BITS: u32 = u32::MAX - (32 + T + O);
     // check we use no less than 32 bits
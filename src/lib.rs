@@ -1,4 +1,7 @@
 pub mod errors;
+pub mod schema;
+pub mod diagnostics;
+pub mod completion;
 
 #[cfg(feature = "indexer")]
 pub mod indexer;
@@ -12,11 +15,28 @@ mod tokenizers;
 mod parsers;
 mod transformers;
 
+#[cfg(test)]
+mod golden;
+
 pub use tokenizers::tokenizer;
+pub use tokenizers::tokenizer_with_schema;
+pub use tokenizers::tokenizer_with_dialect;
+pub use tokenizers::Dialect;
 pub use tokenizers::tokenizers;
 pub use parsers::parser;
 pub use parsers::parsers;
+pub use parsers::parser_with_lines;
+pub use parsers::LineCombinator;
+pub use parsers::parser_with_binding_powers;
+pub use parsers::BindingPowers;
 pub use transformers::transformer;
 pub use transformers::transformers;
+pub use transformers::transformer_with_clock;
+pub use transformers::{Clock, SystemClock};
 
-pub use span::TokenSpan;
\ No newline at end of file
+pub use schema::Schema;
+pub use diagnostics::Diagnostic;
+pub use completion::{complete, complete_with, Suggestion};
+pub use span::TokenSpan;
+pub use tokens::Token;
+pub use ast::CombOp;
\ No newline at end of file
@@ -1,7 +1,8 @@
 
-use std::io::BufWriter;
+use std::{io::BufWriter, path::PathBuf, sync::Arc};
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
 #[derive(Debug, clap::Parser)]
 pub struct App {
@@ -13,17 +14,61 @@ pub struct App {
     file: bool,
 
     #[clap(long, short, default_value = "fsm")]
-    /// Select the tokenizer to use
+    /// Select the tokenizer to use, only fsm supports --dialect and --implicit-and
     tokenizer: String,
     #[clap(long, short = 'o', default_value = "esq")]
     /// Output Data
     transformer: String,
     #[clap(long, short, default_value = "shift_reduce")]
+    /// Select the parser to use, only pratt supports --binding-powers
     parser: String,
+    #[clap(long, value_parser = parse_now)]
+    /// Resolve relative dates against this RFC3339 timestamp instead of the current time
+    now: Option<OffsetDateTime>,
+    #[clap(long)]
+    /// Load the searchable fields from this file, JSON or in the format of samples/db_fields.txt
+    schema: Option<PathBuf>,
+    #[clap(long)]
+    /// Spell the operators as in this JSON file, such as {"and": ["and", "&"]}, using the fsm tokenizer
+    dialect: Option<PathBuf>,
+    #[clap(long)]
+    /// Bind the operators as in this JSON file, such as {"or": [5, 6]}, using the pratt parser
+    binding_powers: Option<PathBuf>,
+    #[clap(long)]
+    /// Combine adjacent terms with AND, tags with spaces must then be quoted
+    implicit_and: bool,
+    #[clap(long, value_enum, default_value = "or")]
+    /// How the clauses on separate lines of the search term are combined
+    lines: search_parser::LineCombinator,
+    #[clap(long, value_enum, default_value = "text")]
+    /// How errors in the search term are reported on stderr
+    error_format: ErrorFormat,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum ErrorFormat {
+    /// The search term with the failing part underlined
+    Text,
+    /// A JSON object with the error code, message, byte range and hint
+    Json,
+}
+
+fn parse_now(inp: &str) -> Result<OffsetDateTime, time::error::Parse> {
+    OffsetDateTime::parse(inp, &Rfc3339)
 }
 
 fn main() -> search_parser::errors::Result<()> {
     let app = App::parse();
+    if (app.dialect.is_some() || app.implicit_and) && app.tokenizer != "fsm" {
+        App::command()
+            .error(clap::error::ErrorKind::ArgumentConflict, "--dialect and --implicit-and need the fsm tokenizer")
+            .exit();
+    }
+    if app.binding_powers.is_some() && app.parser != "pratt" {
+        App::command()
+            .error(clap::error::ErrorKind::ArgumentConflict, "--binding-powers needs the pratt parser")
+            .exit();
+    }
     let term = if app.file {
         let st = std::fs::read_to_string(app.term).unwrap();
         println!("loaded file ({} bytes)", st.len());
@@ -34,9 +79,41 @@ fn main() -> search_parser::errors::Result<()> {
     let output = std::io::stdout();
     let output = BufWriter::new(output);
     let output = Box::new(output);
-    let tokenizer = search_parser::tokenizer(&app.tokenizer, &*term)?;
-    let parser = search_parser::parser(&app.parser, tokenizer)?;
-    let mut transformer = search_parser::transformer(&app.transformer, parser)?;
-
-    transformer.run(output)
+    let schema = Arc::new(match app.schema {
+        Some(ref path) => search_parser::Schema::load(path)?,
+        None => search_parser::Schema::default(),
+    });
+    let dialect = match app.dialect {
+        Some(ref path) => Some(search_parser::Dialect::load(path)?),
+        None if app.implicit_and => Some(search_parser::Dialect::default()),
+        None => None,
+    };
+    let tokenizer = match dialect {
+        Some(dialect) if app.implicit_and => Ok(search_parser::tokenizer_with_dialect(&term, schema.clone(), dialect.with_implicit_and())),
+        Some(dialect) => Ok(search_parser::tokenizer_with_dialect(&term, schema.clone(), dialect)),
+        None => search_parser::tokenizer_with_schema(&app.tokenizer, &term, schema.clone()),
+    };
+    let powers = match app.binding_powers {
+        Some(ref path) => Some(search_parser::BindingPowers::load(path)?),
+        None => None,
+    };
+    let result = tokenizer
+        .and_then(|tokenizer| match powers {
+            Some(powers) => search_parser::parser_with_binding_powers(tokenizer, app.lines, powers),
+            None => search_parser::parser_with_lines(&app.parser, tokenizer, app.lines),
+        })
+        .and_then(|parser| match app.now {
+            Some(now) => search_parser::transformer_with_clock(&app.transformer, parser, &now),
+            None => search_parser::transformer(&app.transformer, parser),
+        })
+        .and_then(|mut transformer| transformer.run(output));
+    if let Err(e) = result {
+        let diagnostic = search_parser::Diagnostic::new(&e, &schema);
+        match app.error_format {
+            ErrorFormat::Text => eprint!("{}", diagnostic.render(&term)),
+            ErrorFormat::Json => eprintln!("{}", diagnostic.to_json()?),
+        }
+        std::process::exit(1);
+    }
+    Ok(())
 }
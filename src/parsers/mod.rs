@@ -1,16 +1,50 @@
-use crate::{errors, tokenizers::ITokenizer, ast::Expr, span::TokenSpan};
+use std::sync::Arc;
+
+use crate::{errors, tokenizers::ITokenizer, ast::{parse_absolute_date, parse_unquoted, unescape, unquote, untag, ApplyOp, CombOp, Comp, Expr, SpanTree, Spanned, Value}, span::{Span, TokenSpan}, tokens::Token};
+use crate::schema::{FieldDef, FieldType, Schema};
 
 mod shift_reduce;
 mod recdec;
+mod pratt;
+
+pub use pratt::BindingPowers;
+
+/// How the clauses on separate lines of a query are combined
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LineCombinator {
+    /// All lines must match
+    And,
+    /// Any line may match, as saved searches always did
+    #[default]
+    Or,
+}
+
+impl From<LineCombinator> for CombOp {
+    fn from(value: LineCombinator) -> Self {
+        match value {
+            LineCombinator::And => CombOp::And,
+            LineCombinator::Or => CombOp::Or,
+        }
+    }
+}
 
 pub trait IParserFactory {
     fn init() -> Box<dyn IParserFactory> where Self: Sized;
-    fn new(&self, tokenizer: Box<dyn ITokenizer>) -> errors::Result<Box<dyn IParser>>;
+    fn new(&self, tokenizer: Box<dyn ITokenizer>, schema: Arc<Schema>, lines: LineCombinator) -> errors::Result<Box<dyn IParser>>;
 }
 
-pub trait IParser {
-    fn produce_tree(&mut self) -> errors::Result<Expr>;
+pub trait IParser: Send + Sync {
+    fn produce_tree(&mut self) -> errors::Result<Expr> {
+        Ok(self.produce_spanned_tree()?.node)
+    }
+    /// Like [IParser::produce_tree] but with the part of the input each node was parsed from
+    fn produce_spanned_tree(&mut self) -> errors::Result<Spanned<Expr>>;
     fn produce_token_sequence(&mut self) -> errors::Result<Vec<TokenSpan>>;
+    /// Like [IParser::produce_token_sequence] but input that cannot be scanned
+    /// is covered by [Token::ERROR] tokens instead of failing
+    fn produce_recovering_token_sequence(&mut self) -> (Vec<TokenSpan>, Vec<errors::Error>);
+    /// The fields the tree was checked against, passed on to the transformer
+    fn schema(&self) -> Arc<Schema>;
 }
 
 pub struct Parser {
@@ -33,11 +67,846 @@ pub fn parsers() -> Vec<String> {
     inventory::iter::<Parser>().map(|x| x.name.to_string()).collect()
 }
 
+/// Creates the named parser, checking fields against the schema of the tokenizer
 pub fn parser(name: &str, tok: Box<dyn crate::tokenizers::ITokenizer>) -> errors::Result<Box<dyn IParser>> {
+    parser_with_lines(name, tok, LineCombinator::default())
+}
+
+/// Like [parser] but the lines of the query are combined as given
+pub fn parser_with_lines(name: &str, tok: Box<dyn crate::tokenizers::ITokenizer>, lines: LineCombinator) -> errors::Result<Box<dyn IParser>> {
     for par in inventory::iter::<Parser> {
         if par.name == name {
-            return Ok((par.imp)().new(tok)?)
+            let schema = tok.schema();
+            return Ok((par.imp)().new(tok, schema, lines)?)
         }
     }
     Err(errors::Error::UnknownTokenizer(name.to_string()))
-}
\ No newline at end of file
+}
+
+/// Like [parser_with_lines] for the `pratt` parser with the operators binding as given
+pub fn parser_with_binding_powers(tok: Box<dyn crate::tokenizers::ITokenizer>, lines: LineCombinator, powers: BindingPowers) -> errors::Result<Box<dyn IParser>> {
+    let schema = tok.schema();
+    pratt::ParserFactory::with_binding_powers(powers).new(tok, schema, lines)
+}
+
+/// Splits the tokens at the line breaks between clauses into the tokens of
+/// each line, starting with a [Token::ROOT] and ending in [Token::EOI] like a
+/// query of their own
+///
+/// Lines are split off as the tokens are scanned, so an error stops the scan
+/// before the lines after it. Clauses cannot be separated by lines inside of
+/// parentheses.
+struct Lines<I> {
+    tokens: I,
+    /// The root of the whole query
+    root: Option<TokenSpan>,
+    count: usize,
+    depth: usize,
+    done: bool,
+}
+
+fn lines<I: Iterator<Item = errors::Result<TokenSpan>>>(tokens: I) -> Lines<I> {
+    Lines {
+        tokens,
+        root: None,
+        count: 0,
+        depth: 0,
+        done: false,
+    }
+}
+
+impl<I> Lines<I> {
+    /// The root of the whole query, once the first line was split off
+    fn root(&self) -> errors::Result<&TokenSpan> {
+        self.root.as_ref().ok_or_else(|| errors::Error::ExpectedTokensNotFound(vec![Token::ROOT]))
+    }
+}
+
+impl<I: Iterator<Item = errors::Result<TokenSpan>>> Iterator for Lines<I> {
+    type Item = errors::Result<Vec<TokenSpan>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut line: Vec<TokenSpan> = Vec::new();
+        loop {
+            let token = match self.tokens.next() {
+                Some(Ok(token)) => token,
+                Some(Err(e)) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+                None => {
+                    self.done = true;
+                    let missing = if self.root.is_none() { Token::ROOT } else { Token::EOI };
+                    return Some(Err(errors::Error::ExpectedTokensNotFound(vec![missing])));
+                }
+            };
+            match token.token() {
+                Token::ROOT => {
+                    self.root = Some(token);
+                    continue;
+                }
+                Token::LPAREN => self.depth += 1,
+                Token::RPAREN => self.depth = self.depth.saturating_sub(1),
+                Token::NEWLINE if self.depth > 0 => {
+                    self.done = true;
+                    return Some(Err(errors::Error::UnexpectedToken(token)));
+                }
+                Token::NEWLINE | Token::EOI => {
+                    self.done = token.token() == Token::EOI;
+                    let root = match (line.first(), line.last(), &self.root) {
+                        // a query of a single line keeps its root
+                        (_, _, Some(root)) if self.done && self.count == 0 => root.clone(),
+                        (Some(first), Some(last), _) => TokenSpan::from_span(Token::ROOT, first.span().join(last.span())),
+                        _ => TokenSpan::from_span(Token::ROOT, token.span().clone()),
+                    };
+                    self.count += 1;
+                    line.insert(0, root);
+                    line.push(TokenSpan::from_span(Token::EOI, token.span().clone()));
+                    return Some(Ok(line));
+                }
+                _ => (),
+            }
+            line.push(token);
+        }
+    }
+}
+
+/// Combines the trees parsed from each line of the query spanning `root`
+fn combine_lines(mut lines: Vec<Spanned<Expr>>, comb: LineCombinator, root: &Span) -> Spanned<Expr> {
+    if lines.len() == 1 {
+        return lines.remove(0);
+    }
+    let (nodes, spans) = lines
+        .into_iter()
+        .map(|line| match line {
+            // a line of a single expression is combined as that expression
+            Spanned { node: Expr::Group(mut g), spans: SpanTree { mut children, .. } } if g.len() == 1 && children.len() == 1 => {
+                (g.remove(0), children.remove(0))
+            }
+            line => (line.node, line.spans),
+        })
+        .unzip();
+    Spanned::new(Expr::Combine(comb.into(), nodes), SpanTree::node(root.clone(), spans))
+}
+
+/// Combines the operands of a chain of the same operator into a single node
+///
+/// Operands combined by the same operator themselves, such as `(a OR b)` in
+/// `(a OR b) OR c`, are merged into it and operands equal to an earlier one
+/// widen its span instead, so the result does not depend on how the chain
+/// was grouped. If a single operand is left, it is returned on its own.
+fn combine_all(op: CombOp, first: Spanned<Expr>, rest: Vec<Spanned<Expr>>) -> Spanned<Expr> {
+    let mut span = first.spans.span.clone();
+    let mut nodes: Vec<Expr> = Vec::new();
+    let mut children: Vec<SpanTree> = Vec::new();
+    for operand in std::iter::once(first).chain(rest) {
+        span = span.join(&operand.spans.span);
+        let parts: Vec<_> = match operand.node {
+            Expr::Combine(inner, items) if inner == op => items.into_iter().zip(operand.spans.children).collect(),
+            node => vec![(node, operand.spans)],
+        };
+        for (node, spans) in parts {
+            match nodes.iter().position(|n| *n == node) {
+                Some(i) => children[i].span = children[i].span.join(&spans.span),
+                None => {
+                    nodes.push(node);
+                    children.push(spans);
+                }
+            }
+        }
+    }
+    if nodes.len() == 1 {
+        return Spanned::new(nodes.remove(0), children.remove(0));
+    }
+    Spanned::new(Expr::Combine(op, nodes), SpanTree::node(span, children))
+}
+
+/// Applies the operator to the expression, spanning both
+fn apply(op: ApplyOp, e: Spanned<Expr>, op_token: &TokenSpan) -> Spanned<Expr> {
+    Spanned::new(
+        Expr::Apply(op, Box::new(e.node)),
+        SpanTree::node(e.spans.span.join(op_token.span()), vec![e.spans]),
+    )
+}
+
+/// The comparison of the field with the value following the range, or the
+/// pattern of a [Token::WILDCARD] value
+fn comparison(schema: &Schema, field: &TokenSpan, range: &TokenSpan, value: &TokenSpan) -> errors::Result<Spanned<Expr>> {
+    if range.token() != Token::RANGE {
+        return Err(unexpected(range));
+    }
+    let def = field_def(schema, field)?;
+    let expr = match value.token() {
+        Token::WILDCARD => field_wildcard(def, value)?,
+        Token::INTEGER
+        | Token::FLOAT
+        | Token::BOOLEAN
+        | Token::IP_CIDR
+        | Token::ABSOLUTE_DATE
+        | Token::RELATIVE_DATE
+        | Token::QUOTED_TERM
+        | Token::UNQUOTED_TERM => {
+            let comp = field_comp(def, range)?;
+            Expr::Comparison(def.name.clone(), comp, field_value(def, value.clone())?)
+        }
+        _ => return Err(unexpected(value)),
+    };
+    Ok(Spanned::new(expr, SpanTree::leaf(field.span().join(value.span()))))
+}
+
+/// The error for a token that cannot appear where it was found
+fn unexpected(token: &TokenSpan) -> errors::Error {
+    errors::Error::UnexpectedToken(token.clone())
+}
+
+/// How deep groups and operators may be nested in a top-down parser, which
+/// recurses once for every level
+pub const MAX_DEPTH: usize = 128;
+
+/// The position in the tokens of a single line, from the [Token::ROOT] to the
+/// [Token::EOI], of a top-down parser
+struct Cursor<'p> {
+    schema: &'p Schema,
+    tokens: &'p [TokenSpan],
+    position: usize,
+    /// The groups and operators the position is nested in
+    depth: usize,
+}
+
+impl<'p> Cursor<'p> {
+    fn new(schema: &'p Schema, tokens: &'p [TokenSpan]) -> Self {
+        Self {
+            schema,
+            tokens,
+            // skip the root
+            position: 1,
+            depth: 0,
+        }
+    }
+
+    /// Enters the group or operand opened by the token, nesting deeper than
+    /// [MAX_DEPTH] is an error
+    fn enter(&mut self, token: &TokenSpan) -> errors::Result<()> {
+        if self.depth == MAX_DEPTH {
+            return Err(errors::Error::NestedTooDeep(token.clone()));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// The token at the position, the end of the line is an error
+    fn peek(&self) -> errors::Result<&'p TokenSpan> {
+        self.tokens
+            .get(self.position)
+            .ok_or_else(|| errors::Error::ExpectedTokensNotFound(vec![Token::EOI]))
+    }
+
+    fn next(&mut self) -> errors::Result<&'p TokenSpan> {
+        let token = self.peek()?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    /// Consumes the next token if it is one of the given
+    fn next_if(&mut self, tokens: &[Token]) -> errors::Result<Option<&'p TokenSpan>> {
+        match self.peek()? {
+            t if tokens.contains(&t.token()) => self.next().map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A top-down parser, which differ in how they parse the operators between
+/// the groups, tags and comparisons
+trait TopDown<'p> {
+    fn cursor(&mut self) -> &mut Cursor<'p>;
+
+    /// Parses an expression up to the end of the group or line it is in
+    fn expr(&mut self) -> errors::Result<Spanned<Expr>>;
+
+    /// Parses the whole line
+    fn parse(&mut self) -> errors::Result<Spanned<Expr>> {
+        let expr = self.expr()?;
+        match self.cursor().next()? {
+            t if t.token() == Token::EOI => Ok(expr),
+            t => Err(unexpected(t)),
+        }
+    }
+
+    /// Parses a group, tag, pattern or comparison
+    fn primary(&mut self) -> errors::Result<Spanned<Expr>> {
+        let token = self.cursor().next()?;
+        let leaf = |expr| Spanned::new(expr, SpanTree::leaf(token.span().clone()));
+        Ok(match token.token() {
+            Token::LPAREN => {
+                if let Some(r) = self.cursor().next_if(&[Token::RPAREN])? {
+                    return Ok(Spanned::new(Expr::Empty, SpanTree::leaf(token.span().join(r.span()))));
+                }
+                self.cursor().enter(token)?;
+                let inner = self.expr()?;
+                self.cursor().leave();
+                match self.cursor().next()? {
+                    // the parentheses become part of the span of the grouped expression
+                    r if r.token() == Token::RPAREN => Spanned::new(inner.node, inner.spans.join(token.span()).join(r.span())),
+                    // a group that is never closed
+                    r if r.token() == Token::EOI => return Err(unexpected(token)),
+                    r => return Err(unexpected(r)),
+                }
+            }
+            Token::TAG => leaf(Expr::Tag(untag(token.str()))),
+            // a quoted term that is not the value of a comparison is a tag
+            Token::QUOTED_TERM => leaf(Expr::Tag(unquote(token.str()))),
+            Token::WILDCARD => leaf(Expr::Wildcard(None, token.str().to_string())),
+            Token::FIELD => {
+                let cursor = self.cursor();
+                let range = cursor.next()?;
+                comparison(cursor.schema, token, range, cursor.next()?)?
+            }
+            _ => return Err(unexpected(token)),
+        })
+    }
+}
+
+/// The comparison of a [Token::RANGE], regardless of its case
+fn str_to_comp(range: &TokenSpan) -> errors::Result<Comp> {
+    Ok(match range.str().to_ascii_lowercase().as_str() {
+        "lt:" => Comp::LessThan,
+        "lte:" => Comp::LessThanOrEqual,
+        "eq:" => Comp::Equal,
+        "neq:" => Comp::NotEqual,
+        "gt:" => Comp::GreaterThan,
+        "gte:" => Comp::GreaterThanOrEqual,
+        "has:" => Comp::Contains,
+        _ => return Err(errors::Error::UnexpectedToken(range.clone())),
+    })
+}
+
+/// Looks up the field of a [Token::FIELD], without the dot before its range
+fn field_def<'s>(schema: &'s Schema, field: &TokenSpan) -> errors::Result<&'s FieldDef> {
+    let name = field.str().strip_suffix('.').unwrap_or(field.str());
+    schema.field(name).ok_or_else(|| errors::Error::UnknownField(field.clone()))
+}
+
+/// The comparison of a range on the field, `field:value` searches within
+/// text fields and is equality on all others
+fn field_comp(field: &FieldDef, range: &TokenSpan) -> errors::Result<Comp> {
+    match range.str() {
+        ":" if field.ty == FieldType::Text => Ok(Comp::Contains),
+        ":" => Ok(Comp::Equal),
+        _ => str_to_comp(range),
+    }
+}
+
+/// The value a field is compared with, which must be of the type of the field
+///
+/// Text and keyword fields take the text of any value, integers are
+/// accepted by float fields and years by date fields as well.
+fn field_value(field: &FieldDef, value: TokenSpan) -> errors::Result<Value> {
+    let typed = match value.token() {
+        Token::QUOTED_TERM if matches!(field.ty, FieldType::Text | FieldType::Keyword) => {
+            return Ok(Value::String(unquote(value.str())))
+        }
+        _ if matches!(field.ty, FieldType::Text | FieldType::Keyword) => return Ok(Value::String(unescape(value.str()))),
+        Token::UNQUOTED_TERM => parse_unquoted(value.str()),
+        _ => value.clone().try_into()?,
+    };
+    match (field.ty, typed) {
+        (FieldType::Integer, v @ Value::Integer(_))
+        | (FieldType::Float, v @ Value::Float(_))
+        | (FieldType::Date, v @ (Value::AbsoluteDate(..) | Value::RelativeDate(_)))
+        | (FieldType::Ip, v @ Value::IP(_))
+        | (FieldType::Bool, v @ Value::Bool(_)) => Ok(v),
+        (FieldType::Float, Value::Integer(v)) => Ok(Value::Float(v as f64)),
+        // a year on its own is an integer to the tokenizer, but only without a sign
+        (FieldType::Date, Value::Integer(_)) if value.str().bytes().all(|b| b.is_ascii_digit()) => match parse_absolute_date(value.str()) {
+            Some((date, precision)) => Ok(Value::AbsoluteDate(date, precision)),
+            None => Err(errors::Error::TypeMismatch(FieldType::Date, value)),
+        },
+        (ty, _) => Err(errors::Error::TypeMismatch(ty, value)),
+    }
+}
+
+/// A `has:` pattern on the field, only text and keyword fields can be
+/// searched by pattern
+fn field_wildcard(field: &FieldDef, pattern: &TokenSpan) -> errors::Result<Expr> {
+    match field.ty {
+        FieldType::Text | FieldType::Keyword => Ok(Expr::Wildcard(Some(field.name.clone()), pattern.str().to_string())),
+        ty => Err(errors::Error::TypeMismatch(ty, pattern.clone())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::ast::{ApplyOp, Comp, CombOp, DatePrecision, Expr, Value};
+    use crate::errors::{Error, Result};
+    use crate::schema::FieldType;
+    use crate::tokens::Token;
+
+    fn try_parse_all(input: &str) -> Vec<Result<Expr>> {
+        super::parsers()
+            .iter()
+            .map(|name| {
+                let tokenizer = crate::tokenizer("fsm", input)?;
+                super::parser(name, tokenizer)?.produce_tree()
+            })
+            .collect()
+    }
+
+    fn parse_all(input: &str) -> Vec<Expr> {
+        try_parse_all(input).into_iter().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    pub fn test_quoted_term() {
+        for tree in parse_all(r#""cat AND dog" OR bird"#) {
+            assert_eq!(
+                Expr::Combine(CombOp::Or, vec![
+                    Expr::Tag("cat AND dog".to_string()),
+                    Expr::Tag("bird".to_string()),
+                ]),
+                tree
+            );
+        }
+        for tree in parse_all(r#"description.has:"a \"b\"""#) {
+            assert_eq!(
+                Expr::Comparison(
+                    "description".to_string(),
+                    Comp::Contains,
+                    Value::String(r#"a "b""#.to_string()),
+                ),
+                tree
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_ip_cidr() {
+        let net = |s: &str| Value::IP(ip_network::IpNetwork::from_str_truncate(s).unwrap());
+        for tree in parse_all("ip.has:10.0.0.1/8 OR ip.eq:2001:db8::1") {
+            assert_eq!(
+                Expr::Combine(CombOp::Or, vec![
+                    Expr::Comparison("ip".to_string(), Comp::Contains, net("10.0.0.0/8")),
+                    Expr::Comparison("ip".to_string(), Comp::Equal, net("2001:db8::1/128")),
+                ]),
+                tree
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_absolute_date() {
+        let (date, precision) = crate::ast::parse_absolute_date("2023-05").unwrap();
+        for tree in parse_all("created_at.eq:2023-05 OR created_at.lt:2023-01") {
+            assert_eq!(
+                Expr::Combine(CombOp::Or, vec![
+                    Expr::Comparison("created_at".to_string(), Comp::Equal, Value::AbsoluteDate(date, precision)),
+                    Expr::Comparison(
+                        "created_at".to_string(),
+                        Comp::LessThan,
+                        Value::AbsoluteDate(date.replace_month(time::Month::January).unwrap(), DatePrecision::Month),
+                    ),
+                ]),
+                tree
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_relative_date() {
+        for tree in parse_all("created_at.gte:3 days ago AND created_at.lt:1 week from now") {
+            assert_eq!(
+                Expr::Combine(CombOp::And, vec![
+                    Expr::Comparison("created_at".to_string(), Comp::GreaterThanOrEqual, Value::RelativeDate(time::Duration::days(-3))),
+                    Expr::Comparison("created_at".to_string(), Comp::LessThan, Value::RelativeDate(time::Duration::WEEK)),
+                ]),
+                tree
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_postfix_boost_fuzz() {
+        for tree in parse_all("(rose OR tulip~1^2) AND (daisy OR lily)^0.5") {
+            assert_eq!(
+                Expr::Combine(CombOp::And, vec![
+                    Expr::Combine(CombOp::Or, vec![
+                        Expr::Tag("rose".to_string()),
+                        Expr::Apply(ApplyOp::Boost(2.0), Box::new(
+                            Expr::Apply(ApplyOp::Fuzz(1.0), Box::new(Expr::Tag("tulip".to_string())))
+                        )),
+                    ]),
+                    Expr::Apply(ApplyOp::Boost(0.5), Box::new(Expr::Combine(CombOp::Or, vec![
+                        Expr::Tag("daisy".to_string()),
+                        Expr::Tag("lily".to_string()),
+                    ]))),
+                ]),
+                tree
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_colon_field() {
+        for tree in parse_all("(uploader:someone, width:1920) OR (description:big cat, artist:foo)") {
+            assert_eq!(
+                Expr::Combine(CombOp::Or, vec![
+                    Expr::Combine(CombOp::And, vec![
+                        Expr::Comparison("uploader".to_string(), Comp::Equal, Value::String("someone".to_string())),
+                        Expr::Comparison("width".to_string(), Comp::Equal, Value::Integer(1920)),
+                    ]),
+                    Expr::Combine(CombOp::And, vec![
+                        Expr::Comparison("description".to_string(), Comp::Contains, Value::String("big cat".to_string())),
+                        Expr::Tag("artist:foo".to_string()),
+                    ]),
+                ]),
+                tree
+            );
+        }
+        for tree in parse_all(r"uploader:s\(x\) OR uploader:\#1") {
+            assert_eq!(
+                Expr::Combine(CombOp::Or, vec![
+                    Expr::Comparison("uploader".to_string(), Comp::Equal, Value::String("s(x)".to_string())),
+                    Expr::Comparison("uploader".to_string(), Comp::Equal, Value::String("#1".to_string())),
+                ]),
+                tree
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_schema_fields() {
+        for tree in parse_all("first_seen_at.gte:2023 OR aspect_ratio:2") {
+            assert_eq!(
+                Expr::Combine(CombOp::Or, vec![
+                    Expr::Comparison(
+                        "created_at".to_string(),
+                        Comp::GreaterThanOrEqual,
+                        Value::AbsoluteDate(crate::ast::parse_absolute_date("2023").unwrap().0, DatePrecision::Year),
+                    ),
+                    Expr::Comparison("aspect_ratio".to_string(), Comp::Equal, Value::Float(2.0)),
+                ]),
+                tree
+            );
+        }
+        for tree in parse_all("uploader.eq:10") {
+            assert_eq!(Expr::Comparison("uploader".to_string(), Comp::Equal, Value::String("10".to_string())), tree);
+        }
+        for res in try_parse_all("artist.eq:foo") {
+            assert!(matches!(res, Err(Error::UnknownField(f)) if f == "artist."));
+        }
+        for res in try_parse_all("score.gte:abc") {
+            assert!(matches!(res, Err(Error::TypeMismatch(FieldType::Integer, v)) if v == "abc"));
+        }
+        for res in try_parse_all("width:wide") {
+            assert!(matches!(res, Err(Error::TypeMismatch(FieldType::Integer, v)) if v == "wide"));
+        }
+        for res in try_parse_all("created_at:-100") {
+            assert!(matches!(res, Err(Error::TypeMismatch(FieldType::Date, v)) if v == "-100"));
+        }
+    }
+
+    #[test]
+    pub fn test_wildcard() {
+        for tree in parse_all("pony* OR description.has:big c?t") {
+            assert_eq!(
+                Expr::Combine(CombOp::Or, vec![
+                    Expr::Wildcard(None, "pony*".to_string()),
+                    Expr::Wildcard(Some("description".to_string()), "big c?t".to_string()),
+                ]),
+                tree
+            );
+        }
+        for result in try_parse_all("width.has:1*") {
+            assert!(matches!(result, Err(Error::TypeMismatch(FieldType::Integer, _))));
+        }
+        // an escaped wildcard is the character itself
+        for tree in parse_all(r"faved_by:foo* OR faved_by:foo\* OR rose\?") {
+            assert_eq!(
+                Expr::Combine(CombOp::Or, vec![
+                    Expr::Wildcard(Some("faved_by".to_string()), "foo*".to_string()),
+                    Expr::Comparison("faved_by".to_string(), Comp::Equal, Value::String("foo*".to_string())),
+                    Expr::Tag("rose?".to_string()),
+                ]),
+                tree
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_lines() {
+        let input = "# saved search\nsafe\n\ncute OR rose # flowers\n";
+        for name in super::parsers() {
+            let tokenizer = crate::tokenizer("fsm", input).unwrap();
+            let tree = super::parser_with_lines(&name, tokenizer, super::LineCombinator::And).unwrap().produce_tree().unwrap();
+            assert_eq!(
+                Expr::Combine(CombOp::And, vec![
+                    Expr::Tag("safe".to_string()),
+                    Expr::Combine(CombOp::Or, vec![
+                        Expr::Tag("cute".to_string()),
+                        Expr::Tag("rose".to_string()),
+                    ]),
+                ]),
+                tree,
+                "{name}"
+            );
+        }
+        for result in try_parse_all("(safe\ncute)") {
+            assert!(matches!(result, Err(Error::UnexpectedToken(t)) if t.token() == Token::NEWLINE));
+        }
+    }
+
+    #[test]
+    pub fn test_lines_stop_at_error() {
+        let mut tokenizer = crate::tokenizer("fsm", "safe\n(cute\nrose)\nbird").unwrap();
+        let mut scanned = Vec::new();
+        let mut lines = super::lines(tokenizer.spans().inspect(|t| scanned.extend(t.as_ref().ok().map(|t| t.token()))));
+        let first = lines.next().unwrap().unwrap();
+        assert_eq!(vec!["safe", "safe", "\n"], first.iter().map(|t| t.str()).collect::<Vec<_>>());
+        assert!(matches!(lines.next(), Some(Err(Error::UnexpectedToken(t))) if t.range() == (10..11)));
+        assert!(lines.next().is_none());
+        drop(lines);
+        assert_eq!(vec![Token::ROOT, Token::TAG, Token::NEWLINE, Token::LPAREN, Token::TAG, Token::NEWLINE], scanned);
+    }
+
+    #[test]
+    pub fn test_spans() {
+        let input = "safe, (cute || width.gte:100^2)";
+        for name in super::parsers() {
+            let tokenizer = crate::tokenizer("fsm", input).unwrap();
+            let tree = super::parser(&name, tokenizer).unwrap().produce_spanned_tree().unwrap();
+            let Expr::Combine(CombOp::And, _) = tree.node else {
+                panic!("{name}: {:?}", tree.node);
+            };
+            let ranges = |spans: &crate::ast::SpanTree| spans.children.iter().map(|s| s.span.range()).collect::<Vec<_>>();
+            assert_eq!(0..31, tree.spans.span.range(), "{name}");
+            assert_eq!(vec![0..4, 6..31], ranges(&tree.spans), "{name}");
+            let or = &tree.spans.children[1];
+            assert_eq!(vec![7..11, 15..30], ranges(or), "{name}");
+            assert_eq!(vec![15..28], ranges(&or.children[1]), "{name}");
+        }
+    }
+
+    #[test]
+    pub fn test_send_across_threads() {
+        for name in super::parsers() {
+            let tokenizer = crate::tokenizer("fsm", "safe, (cute || rose)").unwrap();
+            let parser = std::thread::spawn(move || super::parser(&name, tokenizer).unwrap()).join().unwrap();
+            let transformer = std::thread::spawn(move || crate::transformer("esq", parser).unwrap()).join().unwrap();
+            let tokens = crate::tokenizer("fsm", "safe").unwrap().token_spans().unwrap();
+            std::thread::scope(|s| {
+                s.spawn(|| assert_eq!("safe", tokens[1].str()));
+                s.spawn(move || transformer).join().unwrap().run(Box::new(std::io::sink())).unwrap();
+            });
+        }
+    }
+
+    #[test]
+    pub fn test_implicit_and() {
+        let dialect = crate::Dialect::default().with_implicit_and();
+        for name in super::parsers() {
+            for (implicit, explicit) in [("(red rose) OR tulip", "(red AND rose) OR tulip"), ("red rose width.gt:3", "red AND rose AND width.gt:3")] {
+                let tokenizer = crate::tokenizer_with_dialect(implicit, Arc::default(), dialect.clone());
+                let tree = super::parser(&name, tokenizer).unwrap().produce_tree().unwrap();
+                let expected = super::parser(&name, crate::tokenizer("fsm", explicit).unwrap()).unwrap().produce_tree().unwrap();
+                assert_eq!(expected, tree, "{name}: {implicit}");
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_precedence() {
+        for tree in parse_all("rose OR NOT tulip^2 AND daisy OR lily AND (safe OR cute)") {
+            assert_eq!(
+                Expr::Combine(CombOp::Or, vec![
+                    Expr::Tag("rose".to_string()),
+                    Expr::Combine(CombOp::And, vec![
+                        Expr::Apply(ApplyOp::Not, Box::new(Expr::Apply(ApplyOp::Boost(2.0), Box::new(Expr::Tag("tulip".to_string()))))),
+                        Expr::Tag("daisy".to_string()),
+                    ]),
+                    Expr::Combine(CombOp::And, vec![
+                        Expr::Tag("lily".to_string()),
+                        Expr::Combine(CombOp::Or, vec![Expr::Tag("safe".to_string()), Expr::Tag("cute".to_string())]),
+                    ]),
+                ]),
+                tree
+            );
+        }
+        // equal operands are only folded within the same operator
+        for tree in parse_all("rose AND rose OR rose AND tulip") {
+            assert_eq!(
+                Expr::Combine(CombOp::Or, vec![
+                    Expr::Tag("rose".to_string()),
+                    Expr::Combine(CombOp::And, vec![Expr::Tag("rose".to_string()), Expr::Tag("tulip".to_string())]),
+                ]),
+                tree
+            );
+        }
+    }
+
+    /// How the registered parsers disagree on the tree or error of the query, if they do
+    fn disagreement(query: &str) -> Option<String> {
+        let trees: Vec<_> = inventory::iter::<super::Parser>
+            .into_iter()
+            .map(|par| {
+                let tokenizer = crate::tokenizer("fsm", query).unwrap();
+                let schema = tokenizer.schema();
+                let tree = (par.imp)()
+                    .new(tokenizer, schema, super::LineCombinator::default())
+                    .and_then(|mut p| p.produce_spanned_tree());
+                (par.name, tree.map_err(|e| e.to_string()))
+            })
+            .collect();
+        let (first, expected) = trees.first()?;
+        trees
+            .iter()
+            .find(|(_, tree)| tree != expected)
+            .map(|(name, tree)| format!("{query:?}\n{first}: {expected:#?}\n{name}: {tree:#?}"))
+    }
+
+    #[test]
+    pub fn test_parsers_agree() {
+        for sample in crate::golden::samples("horrors.json") {
+            if let Some(report) = disagreement(&sample.query) {
+                panic!("{report}");
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_misplaced_tokens() {
+        let cases = [
+            // a group that is never closed, even with an operator waiting for its operand
+            ("NOT ( rose", 4..5),
+            ("NOT (rose OR (tulip AND daisy)", 4..5),
+            ("GTE: AND ( GTE:", 9..10),
+            ("rose )", 5..6),
+        ];
+        for (input, range) in cases {
+            for result in try_parse_all(input) {
+                assert!(matches!(&result, Err(Error::UnexpectedToken(t)) if t.range() == range), "{input}: {result:?}");
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_nested_too_deep() {
+        let nested = |open: &str, depth: usize| format!("{}rose{}", open.repeat(depth), ")".repeat(depth));
+        for name in ["recdec", "pratt"] {
+            let parse = |input: &str| super::parser(name, crate::tokenizer("fsm", input).unwrap()).unwrap().produce_tree();
+            assert!(parse(&nested("(", super::MAX_DEPTH)).is_ok(), "{name}");
+            // the group opened one level too deep is reported
+            let result = parse(&nested("(", 1_000));
+            assert!(matches!(&result, Err(Error::NestedTooDeep(t)) if t.range() == (super::MAX_DEPTH..super::MAX_DEPTH + 1)), "{name}: {result:?}");
+            let result = parse(&nested("NOT (", 1_000));
+            assert!(matches!(&result, Err(Error::NestedTooDeep(_))), "{name}: {result:?}");
+        }
+    }
+
+    #[test]
+    pub fn test_binding_powers() {
+        let tag = |t: &str| Expr::Tag(t.to_string());
+        let parse = |input: &str, powers| {
+            let tokenizer = crate::tokenizer("fsm", input).unwrap();
+            super::parser_with_binding_powers(tokenizer, super::LineCombinator::default(), powers).unwrap().produce_tree().unwrap()
+        };
+        let or_first = super::BindingPowers::default().with_or(5, 6);
+        assert_eq!(
+            Expr::Combine(CombOp::And, vec![tag("rose"), Expr::Combine(CombOp::Or, vec![tag("tulip"), tag("daisy")])]),
+            parse("rose AND tulip OR daisy", or_first)
+        );
+        // a boost binding less than AND boosts the whole chain in front of it
+        let loose_boost = super::BindingPowers::from_json(r#"{"boost": [5, 0]}"#).unwrap();
+        assert_eq!(loose_boost, super::BindingPowers::default().with_boost(5, 0));
+        assert_eq!(
+            Expr::Apply(ApplyOp::Boost(2.0), Box::new(Expr::Combine(CombOp::And, vec![tag("rose"), tag("tulip")]))),
+            parse("rose AND tulip^2", loose_boost)
+        );
+        assert_eq!(parse_all("NOT rose^2 OR tulip")[0], parse("NOT rose^2 OR tulip", super::BindingPowers::default()));
+    }
+
+    /// Runs the input through every tokenizer, parser and transformer
+    fn run_all(input: &str) {
+        let now = time::macros::datetime!(2024-01-10 12:00 UTC);
+        for tok in crate::tokenizers() {
+            let (tokens, _) = crate::tokenizer(&tok, input).unwrap().recovering_token_spans();
+            assert_eq!(Some(Token::EOI), tokens.last().map(|t| t.token()), "{tok}: {tokens:?}");
+            let implicit = crate::Dialect::default().with_implicit_and();
+            for par in super::parsers() {
+                let tokenizer = crate::tokenizer_with_dialect(input, Arc::default(), implicit.clone());
+                let _ = super::parser(&par, tokenizer).and_then(|mut p| p.produce_tree());
+            }
+            for par in super::parsers() {
+                for tra in crate::transformers() {
+                    let _ = crate::tokenizer(&tok, input)
+                        .and_then(|t| super::parser(&par, t))
+                        .and_then(|p| crate::transformer_with_clock(&tra, p, &now))
+                        .and_then(|mut t| t.run(Box::new(std::io::sink())));
+                }
+            }
+        }
+    }
+
+    fn query_words() -> impl proptest::strategy::Strategy<Value = String> {
+        use proptest::prelude::*;
+        let word = prop::sample::select(vec![
+            "(", ")", "AND", "OR", "NOT", "-", "!", ",", "&&", "||", "^2", "~1", "\"", "\n", "\r\n", "#", "# c\n",
+            "rose", "artist:foo", "\"a b\"", "score", ".", ":", "score.", "width:", "score.gte:",
+            "created_at.lt:", "GTE:", "has:", "*", "r?se*", "\\*", "10", "-3", "1.5", "true", "yes", "10.0.0.0/8",
+            "2023-05", "3 days ago", "99999999999999999999999999999999999999999", "ä", "",
+        ]);
+        prop::collection::vec(word, 0..12).prop_map(|w| w.join(" "))
+    }
+
+    /// Comparisons of date fields, which the transformers resolve into dates
+    fn date_comparisons() -> impl proptest::strategy::Strategy<Value = String> {
+        use proptest::prelude::*;
+        let field = prop::sample::select(vec!["created_at", "first_seen_at"]);
+        let range = prop::sample::select(vec![":", ".eq:", ".neq:", ".lt:", ".lte:", ".gt:", ".gte:"]);
+        let word = prop_oneof![
+            prop::sample::select(vec![
+                "-100", "+999", "0000", "9999", "9999-12", "9999-12-31T23:59:59-01:00", "0000-01-01T00:00+01:00",
+                "2023", "3 days ago", "8000 years from now", "rose",
+            ]).prop_map(str::to_string),
+            "[0-9+-]{1,5}(-[0-9]{1,2}(-[0-9]{1,2}([T ][0-9]{2}(:[0-9]{2})?(Z|[+-][0-9]{2}(:[0-9]{2})?)?)?)?)?",
+        ];
+        (field, range, word).prop_map(|(f, r, w)| format!("{f}{r}{w}"))
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_no_panic_on_words(input in query_words()) {
+            run_all(&input);
+        }
+
+        #[test]
+        fn test_no_panic_on_dates(input in date_comparisons()) {
+            run_all(&input);
+        }
+
+        #[test]
+        fn test_no_panic_on_text(input in "\\PC{0,40}") {
+            run_all(&input);
+        }
+
+        /// Shrinks a disagreement down to the fewest words showing it
+        #[test]
+        fn test_parsers_agree_on_words(input in query_words()) {
+            let report = disagreement(&input);
+            proptest::prop_assert!(report.is_none(), "{}", report.unwrap_or_default());
+        }
+
+        #[test]
+        fn test_no_panic_on_long_lexemes(input in "[aä\" ]{240,300}") {
+            run_all(&input);
+        }
+    }
+}
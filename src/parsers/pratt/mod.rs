@@ -0,0 +1,228 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::ast::{Expr, Spanned};
+use crate::errors;
+use crate::schema::Schema;
+use crate::span::TokenSpan;
+use crate::tokenizers::ITokenizer;
+use crate::tokens::Token;
+
+use super::{apply, combine_all, combine_lines, lines, Cursor, IParserFactory, LineCombinator, TopDown};
+
+inventory::submit! { super::Parser::new::<ParserFactory>("pratt") }
+
+/// How tightly the operators bind their operands, a higher power binds tighter
+///
+/// Only reweights the operators the tokenizer already knows, OR, AND, NOT,
+/// BOOST and FUZZ, however the [crate::Dialect] spells them. An infix
+/// operator has a power on either side, binding less on its left than on its
+/// right makes a chain of it group to the left. BOOST and FUZZ have a power
+/// in front of the operand they apply to and one after it. The defaults bind
+/// NOT, BOOST and FUZZ tighter than AND, and AND tighter than OR.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct BindingPowers {
+    /// The powers on the left and right of OR
+    or: (u8, u8),
+    /// The powers on the left and right of AND
+    and: (u8, u8),
+    /// The power on the right of NOT
+    not: u8,
+    /// The powers of a BOOST in front of its operand and after it
+    boost: (u8, u8),
+    /// The powers of a FUZZ in front of its operand and after it
+    fuzz: (u8, u8),
+}
+
+impl Default for BindingPowers {
+    fn default() -> Self {
+        Self {
+            or: (1, 2),
+            and: (3, 4),
+            not: 5,
+            // `NOT rose^2` boosts the tag before negating it
+            boost: (5, 7),
+            fuzz: (5, 7),
+        }
+    }
+}
+
+impl BindingPowers {
+    pub fn with_or(mut self, left: u8, right: u8) -> Self {
+        self.or = (left, right);
+        self
+    }
+
+    pub fn with_and(mut self, left: u8, right: u8) -> Self {
+        self.and = (left, right);
+        self
+    }
+
+    pub fn with_not(mut self, right: u8) -> Self {
+        self.not = right;
+        self
+    }
+
+    pub fn with_boost(mut self, prefix: u8, postfix: u8) -> Self {
+        self.boost = (prefix, postfix);
+        self
+    }
+
+    pub fn with_fuzz(mut self, prefix: u8, postfix: u8) -> Self {
+        self.fuzz = (prefix, postfix);
+        self
+    }
+
+    pub fn from_json(inp: &str) -> errors::Result<Self> {
+        Ok(serde_json::from_str(inp)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> errors::Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    fn infix(&self, token: Token) -> Option<(u8, u8)> {
+        match token {
+            Token::OR => Some(self.or),
+            Token::AND => Some(self.and),
+            _ => None,
+        }
+    }
+
+    fn prefix(&self, token: Token) -> Option<u8> {
+        match token {
+            Token::NOT => Some(self.not),
+            Token::BOOST => Some(self.boost.0),
+            Token::FUZZ => Some(self.fuzz.0),
+            _ => None,
+        }
+    }
+
+    fn postfix(&self, token: Token) -> Option<u8> {
+        match token {
+            Token::BOOST => Some(self.boost.1),
+            Token::FUZZ => Some(self.fuzz.1),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ParserFactory {
+    powers: BindingPowers,
+}
+
+impl ParserFactory {
+    pub fn with_binding_powers(powers: BindingPowers) -> Self {
+        Self { powers }
+    }
+}
+
+impl super::IParserFactory for ParserFactory {
+    fn init() -> Box<dyn IParserFactory> where Self: Sized {
+        Box::<Self>::default()
+    }
+
+    fn new(&self, tokenizer: Box<dyn ITokenizer>, schema: Arc<Schema>, lines: LineCombinator) -> errors::Result<Box<dyn super::IParser>> {
+        Ok(Box::new(Parser::new(tokenizer, schema, lines, self.powers.clone())))
+    }
+}
+
+pub struct Parser
+{
+    tokenizer: Box<dyn ITokenizer>,
+    schema: Arc<Schema>,
+    lines: LineCombinator,
+    powers: BindingPowers,
+}
+
+impl super::IParser for Parser {
+    fn produce_spanned_tree(&mut self) -> errors::Result<Spanned<Expr>> {
+        // every line is parsed as a query of its own, scanning stops at the first that fails
+        let mut lines = lines(self.tokenizer.spans());
+        let trees = lines
+            .by_ref()
+            .map(|line| Pratt { cursor: Cursor::new(&self.schema, &line?), powers: &self.powers }.parse())
+            .collect::<errors::Result<Vec<_>>>()?;
+        Ok(combine_lines(trees, self.lines, lines.root()?.span()))
+    }
+    fn produce_token_sequence(&mut self) -> errors::Result<Vec<TokenSpan>> {
+        self.tokenizer.token_spans()
+    }
+    fn produce_recovering_token_sequence(&mut self) -> (Vec<TokenSpan>, Vec<errors::Error>) {
+        self.tokenizer.recovering_token_spans()
+    }
+    fn schema(&self) -> Arc<Schema> {
+        self.schema.clone()
+    }
+}
+
+impl Parser
+{
+    pub fn new(tokenizer: Box<dyn ITokenizer>, schema: Arc<Schema>, lines: LineCombinator, powers: BindingPowers) -> Self {
+        Self {
+            tokenizer,
+            schema,
+            lines,
+            powers,
+        }
+    }
+}
+
+/// Parses the tokens of a single line by top-down operator precedence
+struct Pratt<'p> {
+    cursor: Cursor<'p>,
+    powers: &'p BindingPowers,
+}
+
+impl<'p> TopDown<'p> for Pratt<'p> {
+    fn cursor(&mut self) -> &mut Cursor<'p> {
+        &mut self.cursor
+    }
+
+    fn expr(&mut self) -> errors::Result<Spanned<Expr>> {
+        self.bound(0)
+    }
+}
+
+impl Pratt<'_> {
+    /// The expression up to the first operator binding its left operand
+    /// less than `min`
+    #[tracing::instrument(skip(self))]
+    fn bound(&mut self, min: u8) -> errors::Result<Spanned<Expr>> {
+        let token = self.cursor.peek()?;
+        let mut lhs = match self.powers.prefix(token.token()) {
+            Some(right) => {
+                self.cursor.next()?;
+                self.cursor.enter(token)?;
+                let operand = self.bound(right)?;
+                self.cursor.leave();
+                apply(token.clone().try_into()?, operand, token)
+            }
+            None => self.primary()?,
+        };
+        loop {
+            let op = self.cursor.peek()?;
+            if let Some(left) = self.powers.postfix(op.token()) {
+                if left < min {
+                    break;
+                }
+                self.cursor.next()?;
+                lhs = apply(op.clone().try_into()?, lhs, op);
+            } else if let Some((left, right)) = self.powers.infix(op.token()) {
+                if left < min {
+                    break;
+                }
+                self.cursor.next()?;
+                self.cursor.enter(op)?;
+                let rhs = self.bound(right)?;
+                self.cursor.leave();
+                lhs = combine_all(op.clone().try_into()?, lhs, vec![rhs]);
+            } else {
+                break;
+            }
+        }
+        Ok(lhs)
+    }
+}
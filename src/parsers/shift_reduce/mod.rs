@@ -1,15 +1,17 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
-use crate::ast::{ApplyOp, CombOp, Comp};
+use crate::ast::{unquote, untag, ApplyOp, CombOp, SpanTree, Spanned};
 use crate::errors;
 use crate::tokens::Token;
-use crate::{ast::Expr, span::TokenSpan};
+use crate::{ast::Expr, span::{Span, TokenSpan}};
 
-use super::{IParserFactory, IParser};
+use crate::schema::Schema;
+
+use super::{apply, combine_all, combine_lines, field_comp, field_def, field_value, field_wildcard, lines, unexpected, IParserFactory, IParser, LineCombinator};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenOrExpr {
-    Expr(Expr),
+    Expr(Spanned<Expr>),
     Token(TokenSpan),
 }
 
@@ -20,11 +22,11 @@ impl Default for TokenOrExpr {
 }
 
 impl TokenOrExpr {
-    #[track_caller]
-    pub fn assert_expr(self) -> Expr {
+    /// Returns the expression or an error for the token that was never reduced
+    pub fn into_spanned(self) -> errors::Result<Spanned<Expr>> {
         match self {
-            TokenOrExpr::Expr(v) => v,
-            TokenOrExpr::Token(_) => unreachable!("token in asserted expr"),
+            TokenOrExpr::Expr(v) => Ok(v),
+            TokenOrExpr::Token(t) => Err(errors::Error::UnexpectedToken(t)),
         }
     }
 }
@@ -38,50 +40,104 @@ impl IParserFactory for ShiftReduceFactory {
         Box::new(Self)
     }
 
-    fn new(&self, mut tokenizer: Box<dyn crate::tokenizers::ITokenizer>) -> errors::Result<Box<dyn super::IParser>> {
-        Ok(Box::new(ShiftReduce::new(tokenizer.token_spans()?)))
+    fn new(&self, tokenizer: Box<dyn crate::tokenizers::ITokenizer>, schema: Arc<Schema>, lines: LineCombinator) -> errors::Result<Box<dyn super::IParser>> {
+        Ok(Box::new(Parser { tokenizer, schema, lines }))
     }
 }
 
+/// Pulls the tokens from the tokenizer one line at a time, so scanning
+/// stops at the first line that fails to parse
+struct Parser {
+    tokenizer: Box<dyn crate::tokenizers::ITokenizer>,
+    schema: Arc<Schema>,
+    lines: LineCombinator,
+}
+
+impl IParser for Parser {
+    fn produce_spanned_tree(&mut self) -> errors::Result<Spanned<Expr>> {
+        // every line is parsed as a query of its own
+        let mut lines = lines(self.tokenizer.spans());
+        let trees = lines
+            .by_ref()
+            .map(|line| ShiftReduce::new(line?, self.schema.clone()).parse())
+            .collect::<errors::Result<Vec<_>>>()?;
+        Ok(combine_lines(trees, self.lines, lines.root()?.span()))
+    }
+    fn produce_token_sequence(&mut self) -> errors::Result<Vec<TokenSpan>> {
+        self.tokenizer.token_spans()
+    }
+    fn produce_recovering_token_sequence(&mut self) -> (Vec<TokenSpan>, Vec<errors::Error>) {
+        self.tokenizer.recovering_token_spans()
+    }
+    fn schema(&self) -> Arc<Schema> {
+        self.schema.clone()
+    }
+}
+
+/// How tightly an operator binds its operands, from loosest to tightest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Or,
+    And,
+    /// NOT, BOOST and FUZZ in front of their operand
+    Prefix,
+    /// BOOST and FUZZ following their operand
+    Postfix,
+}
+
+impl Precedence {
+    /// The precedence of the operator following an operand, which is infix or postfix
+    fn after_operand(token: Token) -> Option<Self> {
+        match token {
+            Token::OR => Some(Precedence::Or),
+            Token::AND => Some(Precedence::And),
+            Token::BOOST | Token::FUZZ => Some(Precedence::Postfix),
+            _ => None,
+        }
+    }
+}
+
+/// Parses the tokens of a single line
 struct ShiftReduce {
     input: Vec<TokenSpan>,
     position: usize,
     look_ahead: TokenSpan,
     stack: Vec<TokenOrExpr>,
+    schema: Arc<Schema>,
 }
 
-impl IParser for ShiftReduce {
-    fn produce_tree(&mut self) -> errors::Result<Expr> {
+impl ShiftReduce {
+    pub fn new(input: Vec<TokenSpan>, schema: Arc<Schema>) -> Self {
+        Self {
+            input,
+            position: 0,
+            look_ahead: TokenSpan::new(Arc::from(""), 0..0, Token::NONE),
+            stack: Vec::new(),
+            schema,
+        }
+    }
+
+    fn parse(&mut self) -> errors::Result<Spanned<Expr>> {
         if let Some(next_look_ahead) = self.next_input() {
             self.look_ahead = next_look_ahead;
         } else {
-            panic!("invalid reduce pre-pump");
+            return Err(errors::Error::ExpectedTokensNotFound(vec![Token::EOI]));
         }
         loop {
-            if self.shift().is_none() {
-                if self.reduce().is_none() {
-                    break
-                }
+            if self.shift()?.is_none() && self.reduce()?.is_none() {
+                break
             }
-            while self.reduce().is_some() {
+            while self.reduce()?.is_some() {
                 // reduce more
             }
         }
-        assert!(self.stack.len() == 1, "input left over in parser: {:#?}", self.stack);
-        Ok(self.stack.pop().unwrap().assert_expr())
-    }
-    fn produce_token_sequence(&mut self) -> errors::Result<Vec<TokenSpan>> {
-        Ok(self.input.clone())
-    }
-}
-
-impl ShiftReduce {
-    pub fn new(input: Vec<TokenSpan>) -> Self {
-        Self {
-            input,
-            position: 0,
-            look_ahead: TokenSpan::new(Rc::from(""), 0..0, Token::NONE),
-            stack: Vec::new(),
+        match self.stack.pop() {
+            Some(top) if self.stack.is_empty() => top.into_spanned(),
+            // input left over in the parser, the first token is the one that could not be reduced
+            Some(top) => Err(self.stack.drain(..).chain([top])
+                .find_map(|x| x.into_spanned().err())
+                .unwrap_or_else(|| errors::Error::UnexpectedToken(self.look_ahead.clone()))),
+            None => Err(errors::Error::UnexpectedToken(self.look_ahead.clone())),
         }
     }
 
@@ -90,83 +146,165 @@ impl ShiftReduce {
         self.input.get(self.position).cloned()
     }
 
-    /// Returns an expression only stack or panics
-    #[track_caller]
-    fn expr_stack(&self) -> Vec<Expr> {
+    /// Returns an expression only stack or the first token left on it
+    fn expr_stack(&self) -> errors::Result<Vec<Spanned<Expr>>> {
         self.stack.iter().filter_map(|x| {
             match x {
                 TokenOrExpr::Token(TokenSpan {
                     token: Token::EOI, ..
                 }) => None,
-                v => Some(v.clone().assert_expr()),
+                v => Some(v.clone().into_spanned()),
             }
         }).collect()
     }
 
+    /// Whether the operator on the stack takes the expression in front of the
+    /// look-ahead as its operand, rather than the operator in the look-ahead
+    ///
+    /// Operators of the same precedence are left associative.
+    fn reduces_before_look_ahead(&self, op: Precedence) -> bool {
+        match Precedence::after_operand(self.look_ahead.token()) {
+            Some(ahead) => ahead <= op,
+            None => true,
+        }
+    }
+
+    /// The innermost group on the stack that is not closed yet
+    fn open_group(&self) -> Option<&TokenSpan> {
+        self.stack.iter().rev().find_map(|x| match x {
+            TokenOrExpr::Token(l) if l.token() == Token::LPAREN => Some(l),
+            _ => None,
+        })
+    }
+
+    /// Fails on a look-ahead that cannot follow the top of the stack
+    ///
+    /// Nothing reduces a token out of place, so without this the error only
+    /// shows once the stack cannot be reduced any more, often buried under
+    /// the operators still waiting for their operands.
+    fn check_look_ahead(&self) -> errors::Result<()> {
+        let ahead = self.look_ahead.token();
+        let operand = matches!(
+            ahead,
+            Token::NOT | Token::BOOST | Token::FUZZ | Token::LPAREN | Token::TAG | Token::QUOTED_TERM | Token::WILDCARD | Token::FIELD
+        );
+        let fits = match &self.stack[..] {
+            [.., TokenOrExpr::Expr(_)] => match ahead {
+                Token::AND | Token::OR | Token::BOOST | Token::FUZZ => true,
+                Token::RPAREN => self.open_group().is_some(),
+                // a group that is never closed
+                Token::EOI => return self.open_group().map_or(Ok(()), |l| Err(unexpected(l))),
+                _ => false,
+            },
+            [.., TokenOrExpr::Token(f)] if f.token() == Token::FIELD => ahead == Token::RANGE,
+            [.., TokenOrExpr::Token(f), TokenOrExpr::Token(r)] if r.token() == Token::RANGE => {
+                // an unknown field is reported before its value
+                field_def(&self.schema, f)?;
+                matches!(
+                    ahead,
+                    Token::WILDCARD
+                        | Token::INTEGER
+                        | Token::FLOAT
+                        | Token::BOOLEAN
+                        | Token::IP_CIDR
+                        | Token::ABSOLUTE_DATE
+                        | Token::RELATIVE_DATE
+                        | Token::QUOTED_TERM
+                        | Token::UNQUOTED_TERM
+                )
+            }
+            [.., TokenOrExpr::Token(l)] if l.token() == Token::LPAREN => operand || ahead == Token::RPAREN,
+            _ => operand,
+        };
+        if fits {
+            Ok(())
+        } else {
+            Err(unexpected(&self.look_ahead))
+        }
+    }
+
     /// Shift the next look-ahead into the parser
     ///
-    /// Returns false if no shift was possible
-    fn shift(&mut self) -> Option<()> {
+    /// Returns None if no shift was possible
+    fn shift(&mut self) -> errors::Result<Option<()>> {
         //println!("shift  state: {:?}", self.stack);
+        self.check_look_ahead()?;
         let expr = match self.look_ahead.token() {
-            Token::TAG => TokenOrExpr::Expr(Expr::Tag(self.look_ahead.str().to_string())),
-            Token::EOI => return None,
+            Token::TAG => TokenOrExpr::Expr(Spanned::new(
+                Expr::Tag(untag(self.look_ahead.str())),
+                SpanTree::leaf(self.look_ahead.span().clone()),
+            )),
+            Token::EOI => return Ok(None),
             _ => TokenOrExpr::Token(self.look_ahead.clone()),
         };
         self.stack.push(expr);
-        self.look_ahead = self.next_input()?;
-        Some(())
+        Ok(self.next_input().map(|next| self.look_ahead = next))
     }
 
     /// Reduce the input and lookahead
     ///
     /// Returns None if no reduce was done
-    fn reduce(&mut self) -> Option<()> {
+    fn reduce(&mut self) -> errors::Result<Option<()>> {
         //println!("reduce state: {:?}", self.stack);
         let (rest, result) = match &self.stack[..] {
-            [rest @ .., TokenOrExpr::Token(TokenSpan {
+            [rest @ .., TokenOrExpr::Token(l @ TokenSpan {
                 token: Token::LPAREN,
                 ..
-            }), TokenOrExpr::Token(TokenSpan {
+            }), TokenOrExpr::Token(r @ TokenSpan {
                 token: Token::RPAREN,
                 ..
-            })] => (rest, Expr::Empty),
+            })] => (rest, Spanned::new(Expr::Empty, SpanTree::leaf(l.span().join(r.span())))),
 
-            [rest @ .., TokenOrExpr::Token(TokenSpan {
+            // the parentheses become part of the span of the grouped expression
+            [rest @ .., TokenOrExpr::Token(l @ TokenSpan {
                 token: Token::LPAREN,
                 ..
-            }), TokenOrExpr::Expr(e), TokenOrExpr::Token(TokenSpan {
+            }), TokenOrExpr::Expr(e), TokenOrExpr::Token(r @ TokenSpan {
                 token: Token::RPAREN,
                 ..
-            })] => (rest, e.clone()),
+            })] => (rest, Spanned::new(e.node.clone(), e.spans.clone().join(l.span()).join(r.span()))),
 
-            [TokenOrExpr::Token(TokenSpan {
+            [TokenOrExpr::Token(l @ TokenSpan {
                 token: Token::LPAREN,
                 ..
-            }), ref n @ .., TokenOrExpr::Token(TokenSpan {
+            }), ref n @ .., TokenOrExpr::Token(r @ TokenSpan {
                 token: Token::RPAREN,
                 ..
-            })] => (self.stack.as_slice(), Expr::Group(n.into_iter().map(|x| x.clone().assert_expr()).collect())),
+            })] => {
+                let items = n.iter().map(|x| x.clone().into_spanned()).collect::<errors::Result<Vec<_>>>()?;
+                (self.stack.as_slice(), group(items, l.span().join(r.span())))
+            },
 
-            [rest @ .., TokenOrExpr::Expr(a), TokenOrExpr::Token(TokenSpan {
-                token: Token::AND, ..
-            }), TokenOrExpr::Expr(b)]  if *a == *b => (rest, a.clone()),
+            // a boost or fuzz after an expression belongs to it alone
+            [rest @ .., TokenOrExpr::Expr(e), TokenOrExpr::Token(
+                op @ TokenSpan {
+                    token: Token::BOOST | Token::FUZZ,
+                    ..
+                },
+            )] => (rest, apply(op.clone().try_into()?, e.clone(), op)),
 
             [rest @ .., TokenOrExpr::Expr(a), TokenOrExpr::Token(TokenSpan {
                 token: Token::AND, ..
-            }), TokenOrExpr::Expr(b)] => (rest, Expr::Combine(CombOp::And, vec![a.clone(), b.clone()])),
-
-            [rest @ .., TokenOrExpr::Expr(a), TokenOrExpr::Token(TokenSpan {
-                token: Token::OR, ..
-            }), TokenOrExpr::Expr(b)] if *a == *b => (rest, a.clone()),
+            }), TokenOrExpr::Expr(b)] if self.reduces_before_look_ahead(Precedence::And) => {
+                (rest, combine_all(CombOp::And, a.clone(), vec![b.clone()]))
+            }
 
             [rest @ .., TokenOrExpr::Expr(a), TokenOrExpr::Token(TokenSpan {
                 token: Token::OR, ..
-            }), TokenOrExpr::Expr(b)] => (rest, Expr::Combine(CombOp::Or, vec![a.clone(), b.clone()])),
+            }), TokenOrExpr::Expr(b)] if self.reduces_before_look_ahead(Precedence::Or) => {
+                (rest, combine_all(CombOp::Or, a.clone(), vec![b.clone()]))
+            }
 
-            [rest @ .., TokenOrExpr::Token(TokenSpan {
+            [rest @ .., TokenOrExpr::Token(op @ TokenSpan {
                 token: Token::NOT, ..
-            }), TokenOrExpr::Expr(e) ] => (rest, Expr::Apply(ApplyOp::Not, e.clone().into())),
+            }), TokenOrExpr::Expr(e) ] if self.reduces_before_look_ahead(Precedence::Prefix) => (rest, apply(ApplyOp::Not, e.clone(), op)),
+
+            [rest @ .., TokenOrExpr::Token(
+                op @ TokenSpan {
+                    token: Token::BOOST | Token::FUZZ,
+                    ..
+                },
+            ), TokenOrExpr::Expr(e)] if self.reduces_before_look_ahead(Precedence::Prefix) => (rest, apply(op.clone().try_into()?, e.clone(), op)),
 
             [rest@.., TokenOrExpr::Token(
                 f @ TokenSpan {
@@ -186,70 +324,77 @@ impl ShiftReduce {
                         | Token::BOOLEAN
                         | Token::IP_CIDR
                         | Token::ABSOLUTE_DATE
-                        | Token::RELATIVE_DATE,
+                        | Token::RELATIVE_DATE
+                        | Token::QUOTED_TERM
+                        | Token::UNQUOTED_TERM,
+                    ..
+                },
+            )] => {
+                let field = field_def(&self.schema, f)?;
+                let comp = field_comp(field, c)?;
+                let value = field_value(field, (*v).clone())?;
+                (rest, Spanned::new(Expr::Comparison(field.name.clone(), comp, value), SpanTree::leaf(f.span().join(v.span()))))
+            },
+
+            [rest@.., TokenOrExpr::Token(
+                f @ TokenSpan {
+                    token: Token::FIELD,
+                    ..
+                },
+            ), TokenOrExpr::Token(TokenSpan {
+                token: Token::RANGE,
+                ..
+            }), TokenOrExpr::Token(
+                p @ TokenSpan {
+                    token: Token::WILDCARD,
                     ..
                 },
-            )] => (rest, Expr::Comparison(
-                    f.str().to_string(),
-                    str_to_comp(c.str()),
-                    (*v).clone().into(),
-                )),
+            )] => {
+                let field = field_def(&self.schema, f)?;
+                (rest, Spanned::new(field_wildcard(field, p)?, SpanTree::leaf(f.span().join(p.span()))))
+            },
+
+            // a wildcard that is not the value of a comparison matches tags
+            [rest @ .., TokenOrExpr::Token(
+                p @ TokenSpan {
+                    token: Token::WILDCARD,
+                    ..
+                },
+            )] => (rest, Spanned::new(Expr::Wildcard(None, p.str().to_string()), SpanTree::leaf(p.span().clone()))),
+
+            // a quoted term that is not the value of a comparison is a tag
+            [rest @ .., TokenOrExpr::Token(
+                q @ TokenSpan {
+                    token: Token::QUOTED_TERM,
+                    ..
+                },
+            )] => (rest, Spanned::new(Expr::Tag(unquote(q.str())), SpanTree::leaf(q.span().clone()))),
 
             [.., TokenOrExpr::Token(_c @ TokenSpan {
                 token: Token::EOI,
                 ..
             })] => {
                 if self.stack.len() == 2 {
-                    self.stack = vec![self.stack.get(0).unwrap().clone()];
-                    return None;
+                    self.stack = vec![self.stack[0].clone()];
+                    return Ok(None);
                 }
+                let root = self.input[0].span().clone();
                 self.stack = vec![
-                    TokenOrExpr::Expr(Expr::Group(self.expr_stack()))
+                    TokenOrExpr::Expr(group(self.expr_stack()?, root))
                 ];
-                return None;
-            }
-
-            // ===
-            // Below here we optimize the query by reducing nested operations and combining multiple tag mentions into a single Ast Node
-
-            [rest @ .., TokenOrExpr::Expr(Expr::Combine(CombOp::And, op))] if op.get(0).map(Expr::comb_op).flatten() == Some(CombOp::And) => {
-                let mut new_op = op.clone();
-                let old_op = new_op.remove(0);
-                let mut old_data = match old_op {
-                    Expr::Combine(CombOp::And, data) => data,
-                    _ => unreachable!()
-                };
-                new_op.append(&mut old_data);
-                (rest, Expr::Combine(CombOp::And, new_op))
+                return Ok(None);
             }
 
-            [rest @ .., TokenOrExpr::Expr(Expr::Combine(CombOp::Or, op))] if op.get(0).map(Expr::comb_op).flatten() == Some(CombOp::Or) => {
-                let mut new_op = op.clone();
-                let old_op = new_op.remove(0);
-                let mut old_data = match old_op {
-                    Expr::Combine(CombOp::Or, data) => data,
-                    _ => unreachable!()
-                };
-                new_op.append(&mut old_data);
-                (rest, Expr::Combine(CombOp::Or, new_op))
-            }
-            
-            _ => return None,
+            _ => return Ok(None),
         };
         self.stack.truncate(rest.len());
         self.stack.push(TokenOrExpr::Expr(result));
-        Some(())
+        Ok(Some(()))
     }
 }
 
-fn str_to_comp(a: &str) -> Comp {
-    match a {
-        "lt:" => Comp::LessThan,
-        "lte:" => Comp::LessThanOrEqual,
-        "eq:" => Comp::Equal,
-        "neq:" => Comp::NotEqual,
-        "gt:" => Comp::GreaterThan,
-        "gte:" => Comp::GreaterThanOrEqual,
-        _ => unreachable!(),
-    }
+/// Groups the expressions into a node spanning `span`
+fn group(items: Vec<Spanned<Expr>>, span: Span) -> Spanned<Expr> {
+    let (nodes, spans) = items.into_iter().map(|x| (x.node, x.spans)).unzip();
+    Spanned::new(Expr::Group(nodes), SpanTree::node(span, spans))
 }
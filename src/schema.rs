@@ -0,0 +1,214 @@
+//! Fields that can be searched with `field:value` or `field.gte:value`,
+//! anything else in front of a colon stays part of a tag such as `artist:foo`
+
+use std::path::Path;
+
+use crate::errors;
+
+/// The type of a field, values compared with it must be of this type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Integer,
+    Float,
+    /// An absolute or relative date
+    Date,
+    /// An IP address or network
+    Ip,
+    Bool,
+    /// Text that must be equal as a whole
+    Keyword,
+    /// Text that is searched for within the field by `field:value`
+    Text,
+}
+
+impl FieldType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FieldType::Integer => "integer",
+            FieldType::Float => "float",
+            FieldType::Date => "date",
+            FieldType::Ip => "ip",
+            FieldType::Bool => "bool",
+            FieldType::Keyword => "keyword",
+            FieldType::Text => "text",
+        }
+    }
+}
+
+impl std::str::FromStr for FieldType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "integer" => FieldType::Integer,
+            "float" => FieldType::Float,
+            "date" => FieldType::Date,
+            "ip" => FieldType::Ip,
+            "bool" => FieldType::Bool,
+            "keyword" => FieldType::Keyword,
+            "text" => FieldType::Text,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// A searchable field and the other names it can be searched by
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FieldDef {
+    pub name: String,
+    #[serde(rename = "type", default = "default_type")]
+    pub ty: FieldType,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+impl FieldDef {
+    pub fn new(name: &str, ty: FieldType) -> Self {
+        Self {
+            name: name.to_string(),
+            ty,
+            aliases: Vec::new(),
+        }
+    }
+    pub fn with_alias(mut self, alias: &str) -> Self {
+        self.aliases.push(alias.to_string());
+        self
+    }
+}
+
+/// Fields without a type are compared as a whole
+fn default_type() -> FieldType {
+    FieldType::Keyword
+}
+
+fn default_tag_field() -> String {
+    "tag".to_string()
+}
+
+/// The fields known to the tokenizer, parser and transformers
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Schema {
+    fields: Vec<FieldDef>,
+    /// The field tags are searched in
+    #[serde(default = "default_tag_field")]
+    tag_field: String,
+}
+
+impl Schema {
+    /// A schema without any fields, everything is searched as a tag
+    pub fn empty() -> Self {
+        Self {
+            fields: Vec::new(),
+            tag_field: default_tag_field(),
+        }
+    }
+    pub fn with_field(mut self, field: FieldDef) -> Self {
+        self.fields.push(field);
+        self
+    }
+    pub fn with_tag_field(mut self, tag_field: &str) -> Self {
+        self.tag_field = tag_field.to_string();
+        self
+    }
+    /// Looks up a field by its name or one of its aliases
+    pub fn field(&self, name: &str) -> Option<&FieldDef> {
+        self.fields
+            .iter()
+            .find(|f| f.name == name || f.aliases.iter().any(|a| a == name))
+    }
+    pub fn fields(&self) -> &[FieldDef] {
+        &self.fields
+    }
+    pub fn tag_field(&self) -> &str {
+        &self.tag_field
+    }
+
+    /// Reads the format of `samples/db_fields.txt`, one field per line as
+    /// its name, optionally followed by its type and aliases
+    ///
+    /// Empty lines and anything after a `#` are ignored, fields without
+    /// a type are keywords.
+    pub fn from_text(inp: &str) -> errors::Result<Self> {
+        let mut schema = Self::empty();
+        for (line_no, line) in inp.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            let Some(name) = words.next() else {
+                continue;
+            };
+            let ty = match words.next() {
+                Some(ty) => ty.parse().map_err(|_| errors::Error::InvalidSchema(
+                    line_no + 1,
+                    format!("unknown field type {ty:?}"),
+                ))?,
+                None => default_type(),
+            };
+            let field = words.fold(FieldDef::new(name, ty), FieldDef::with_alias);
+            schema = schema.with_field(field);
+        }
+        Ok(schema)
+    }
+
+    /// Reads a schema serialized as JSON
+    pub fn from_json(inp: &str) -> errors::Result<Self> {
+        Ok(serde_json::from_str(inp)?)
+    }
+
+    /// Loads a schema from a `.json` file or otherwise the text format
+    pub fn load(path: impl AsRef<Path>) -> errors::Result<Self> {
+        let path = path.as_ref();
+        let inp = std::fs::read_to_string(path)?;
+        match path.extension() {
+            Some(ext) if ext == "json" => Self::from_json(&inp),
+            _ => Self::from_text(&inp),
+        }
+    }
+}
+
+/// The fields of `samples/db_fields.txt`
+impl Default for Schema {
+    fn default() -> Self {
+        Self::from_text(include_str!("../samples/db_fields.txt")).expect("sample fields are valid")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FieldDef, FieldType, Schema};
+
+    #[test]
+    pub fn test_text_schema() {
+        let schema = Schema::from_text("# comment\nwidth integer\n\nuploader\ncreated_at date first_seen_at # since\n").unwrap();
+        assert_eq!(
+            Schema::empty()
+                .with_field(FieldDef::new("width", FieldType::Integer))
+                .with_field(FieldDef::new("uploader", FieldType::Keyword))
+                .with_field(FieldDef::new("created_at", FieldType::Date).with_alias("first_seen_at")),
+            schema
+        );
+        assert_eq!(Some("created_at"), schema.field("first_seen_at").map(|f| f.name.as_str()));
+        assert_eq!(None, schema.field("artist"));
+        assert!(Schema::from_text("width number").is_err());
+    }
+
+    #[test]
+    pub fn test_json_schema() {
+        let schema = Schema::from_json(r#"{"fields": [{"name": "ip", "type": "ip", "aliases": ["addr"]}, {"name": "uploader"}], "tag_field": "tags"}"#).unwrap();
+        assert_eq!(
+            Schema::empty()
+                .with_field(FieldDef::new("ip", FieldType::Ip).with_alias("addr"))
+                .with_field(FieldDef::new("uploader", FieldType::Keyword))
+                .with_tag_field("tags"),
+            schema
+        );
+    }
+
+    #[test]
+    pub fn test_default_schema() {
+        let schema = Schema::default();
+        assert_eq!(Some(FieldType::Text), schema.field("description").map(|f| f.ty));
+        assert_eq!(Some(FieldType::Float), schema.field("aspect_ratio").map(|f| f.ty));
+        assert_eq!("tag", schema.tag_field());
+    }
+}
@@ -1,16 +1,16 @@
-use std::{ops::Range, rc::Rc};
+use std::{ops::Range, sync::Arc};
 
 use crate::tokens::Token;
 
 /// A section of a string delimited by start and end position
 #[derive(Clone, Eq)]
 pub struct Span {
-    internal_string: Rc<str>,
+    internal_string: Arc<str>,
     range: (usize, usize),
 }
 
 impl Span {
-    pub fn new(s: Rc<str>, range: Range<usize>) -> Span {
+    pub fn new(s: Arc<str>, range: Range<usize>) -> Span {
         Span {
             internal_string: s,
             range: (range.start, range.end),
@@ -25,6 +25,17 @@ impl Span {
     pub fn is_empty(&self) -> bool {
         (self.range.0..self.range.1).is_empty()
     }
+    /// The byte range of the span in the input
+    pub fn range(&self) -> Range<usize> {
+        self.range.0..self.range.1
+    }
+    /// The span from the start of the earlier to the end of the later span
+    pub fn join(&self, other: &Span) -> Span {
+        Span {
+            internal_string: self.internal_string.clone(),
+            range: (self.range.0.min(other.range.0), self.range.1.max(other.range.1)),
+        }
+    }
 }
 
 impl std::fmt::Debug for Span {
@@ -65,7 +76,7 @@ pub struct TokenSpan {
 }
 
 impl TokenSpan {
-    pub fn new(s: Rc<str>, range: Range<usize>, token: Token) -> TokenSpan {
+    pub fn new(s: Arc<str>, range: Range<usize>, token: Token) -> TokenSpan {
         TokenSpan {
             token,
             span: Span::new(s, range),
@@ -74,7 +85,7 @@ impl TokenSpan {
     pub fn empty() -> TokenSpan {
         TokenSpan {
             token: Token::NONE,
-            span: Span::new(Rc::from(""), 0..0),
+            span: Span::new(Arc::from(""), 0..0),
         }
     }
     pub fn from_span(token: Token, span: Span) -> TokenSpan {
@@ -92,7 +103,7 @@ impl TokenSpan {
         TokenSpan {
             token: self.token,
             span: Span {
-                internal_string: Rc::from(s),
+                internal_string: Arc::from(s),
                 range: self.span.range,
             },
         }
@@ -113,6 +124,13 @@ impl TokenSpan {
     pub fn token(&self) -> Token {
         self.token
     }
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+    /// The byte range of the token in the input
+    pub fn range(&self) -> Range<usize> {
+        self.span.range()
+    }
 }
 
 impl PartialEq<str> for TokenSpan {
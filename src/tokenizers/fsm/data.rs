@@ -1,9 +1,25 @@
 mod ip_cidr;
 mod ipv6_cidr;
+mod quoted;
+mod unquoted;
 
 use regex::Regex;
 
-use super::FSMStateMatcher;
+use super::{dialect::{is_word, Dialect}, FSMStateMatcher};
+
+use self::{
+    ip_cidr::{IPAddress, IPNetwork},
+    quoted::QuotedTerm,
+    unquoted::UnquotedTerm,
+};
+
+/// Whether a literal ends in front of the rest of the input, which is at the
+/// end of the input, whitespace, a `,` or `)` or an operator
+fn ends_literal(rest: &str, dialect: &Dialect) -> bool {
+    rest.is_empty()
+        || rest.starts_with(|c: char| c.is_whitespace() || matches!(c, ',' | ')' | '^' | '~'))
+        || dialect.combinator_at(rest).is_some_and(|k| !is_word(k))
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataValueType {
@@ -15,62 +31,68 @@ pub enum DataValueType {
     RelativeDate,
     AbsoluteDate,
     String,
+    /// The text after `field:` or any other value a comparator does not
+    /// match, typed against the schema by the parser
+    Unquoted,
+    /// An unquoted value containing unescaped `*` or `?`, such as `pony*`
+    Wildcard,
 }
 
-impl FSMStateMatcher for DataValueType {
-    fn matches(self, inp: &str) -> Option<u8> {
+impl DataValueType {
+    /// Returns the length of the value, unquoted values end at the operators of the dialect
+    pub fn matches(self, inp: &str, dialect: &Dialect) -> Option<usize> {
         thread_local! {
             static FLOAT: Regex = Regex::new(r"(?P<float>^[+-]{0,1}\d+\.\d+)").unwrap();
             static INTEGER: Regex = Regex::new(r"(?P<int>^[+-]{0,1}\d+)").unwrap();
             static FIELD: Regex = Regex::new(r"(?P<field>^[^.\(\),\s]+)(?:\s(AND|OR|\.[gl]te?:|\.n?eq)(\s)){0,1}").unwrap();
-            static IP_CIDR: Regex = Regex::new(r"(?P<ip>(\b25[0-5]|\b2[0-4][0-9]|\b[01]?[0-9][0-9]?)(\.(25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)){3}|(([0-9a-fA-F]{1,4}:){7,7}[0-9a-fA-F]{1,4}|([0-9a-fA-F]{1,4}:){1,7}:|([0-9a-fA-F]{1,4}:){1,6}:[0-9a-fA-F]{1,4}|([0-9a-fA-F]{1,4}:){1,5}(:[0-9a-fA-F]{1,4}){1,2}|([0-9a-fA-F]{1,4}:){1,4}(:[0-9a-fA-F]{1,4}){1,3}|([0-9a-fA-F]{1,4}:){1,3}(:[0-9a-fA-F]{1,4}){1,4}|([0-9a-fA-F]{1,4}:){1,2}(:[0-9a-fA-F]{1,4}){1,5}|[0-9a-fA-F]{1,4}:((:[0-9a-fA-F]{1,4}){1,6})|:((:[0-9a-fA-F]{1,4}){1,7}|:)|fe80:(:[0-9a-fA-F]{0,4}){0,4}%[0-9a-zA-Z]{1,}|::(ffff(:0{1,4}){0,1}:){0,1}((25[0-5]|(2[0-4]|1{0,1}[0-9]){0,1}[0-9])\.){3,3}(25[0-5]|(2[0-4]|1{0,1}[0-9]){0,1}[0-9])|([0-9a-fA-F]{1,4}:){1,4}:((25[0-5]|(2[0-4]|1{0,1}[0-9]){0,1}[0-9])\.){3,3}(25[0-5]|(2[0-4]|1{0,1}[0-9]){0,1}[0-9])))(?P<netmask>/\d+)?").unwrap();
             static ABS_DATE: Regex = Regex::new(r"^(?P<year>\d{4}(-(?P<month>\d{2})(-(?P<day>\d{2}))?)?)((T| )(?P<hour>\d{2}(:(?P<minute>\d{2}(:(?P<second>\d{2}))?))?))?(?P<offset_hour>[+-]\d{2}(:(?P<offset_minute>\d{2}))?|(?P<zulu>Z))?").unwrap();
-            static REL_DATE: Regex = Regex::new(r"((?P<years>\d+ years?)\s+)?((?P<months>\d+ months?)\s+)?((?P<weeks>\d+ weeks?)\s+)?((?P<days>\d+ days?)\s+)?((?P<hours>\d+ hours?)\s+)?((?P<minutes>\d+ minutes?)\s+)?((?P<seconds>\d+ seconds?)\s+)?(ago|from now)").unwrap();
+            static REL_DATE: Regex = Regex::new(r"^((?P<years>\d+\s+years?)\s+)?((?P<months>\d+\s+months?)\s+)?((?P<weeks>\d+\s+weeks?)\s+)?((?P<days>\d+\s+days?)\s+)?((?P<hours>\d+\s+hours?)\s+)?((?P<minutes>\d+\s+minutes?)\s+)?((?P<seconds>\d+\s+seconds?)\s+)?(ago|from\s+now)\b").unwrap();
         }
         match self {
             DataValueType::Float => {
-                FLOAT.with(|float| float.find_at(inp, 0).map(|x| x.end() as u8))
+                FLOAT.with(|float| float.find_at(inp, 0).map(|x| x.end()))
             }
             DataValueType::Integer => {
-                INTEGER.with(|int| int.find_at(inp, 0).map(|x| x.end() as u8))
-            }
-            DataValueType::Boolean if inp.to_ascii_lowercase().starts_with("true") => {
-                Some("true".len() as u8)
-            }
-            DataValueType::Boolean if inp.to_ascii_lowercase().starts_with("yes") => {
-                Some("yes".len() as u8)
-            }
-            DataValueType::Boolean if inp.to_ascii_lowercase().starts_with("false") => {
-                Some("false".len() as u8)
-            }
-            DataValueType::Boolean if inp.to_ascii_lowercase().starts_with("no") => {
-                Some("no".len() as u8)
+                INTEGER.with(|int| int.find_at(inp, 0).map(|x| x.end()))
+                    // the year of an absolute date such as 2023-05
+                    .filter(|end| !inp[*end..].strip_prefix('-').is_some_and(|x| x.starts_with(|c: char| c.is_ascii_digit())))
             }
-            DataValueType::Boolean => None,
-            DataValueType::IP => None,   // TODO: implement IP matching
-            DataValueType::CIDR => None, // TODO: implement CIDR matching
+            DataValueType::Boolean => ["true", "yes", "false", "no"]
+                .into_iter()
+                .find(|word| inp.get(..word.len()).is_some_and(|x| x.eq_ignore_ascii_case(word)))
+                .map(str::len)
+                // `nobody` or `yesterday` are words of their own
+                .filter(|end| ends_literal(&inp[*end..], dialect)),
+            DataValueType::IP => IPAddress.matches(inp),
+            DataValueType::CIDR => IPNetwork.matches(inp),
             DataValueType::RelativeDate => {
-                REL_DATE.with(|rel_date| rel_date.find_at(inp, 0).map(|x| x.end() as u8))
+                REL_DATE.with(|rel_date| rel_date.find_at(inp, 0).map(|x| x.end()))
             }
             DataValueType::AbsoluteDate => {
-                ABS_DATE.with(|abs_date| abs_date.find_at(inp, 0).map(|x| x.end() as u8))
+                ABS_DATE.with(|abs_date| abs_date.find_at(inp, 0).map(|x| x.end()))
+                    // a date running into more digits is a longer number instead
+                    .filter(|end| !inp[*end..].starts_with(|c: char| c.is_ascii_digit() || c == '.' || c == ':'))
             }
-            DataValueType::String => None, // TODO: implement String matching
+            DataValueType::String => QuotedTerm.matches(inp),
+            DataValueType::Unquoted => UnquotedTerm(dialect).matches(inp),
+            DataValueType::Wildcard => UnquotedTerm(dialect)
+                .matches(inp)
+                .filter(|len| super::token_and_field::has_wildcard(&inp[..*len])),
         }
     }
 
-    fn maximum_bound(self) -> Option<u8> {
+    pub fn maximum_bound(self) -> Option<usize> {
         match self {
             DataValueType::Float => None,
             DataValueType::Integer => None,
-            DataValueType::Boolean => Some(3),
-            // An IPv6 can only be this long at max, so this is what we need at worst
-            DataValueType::IP => Some("0000:0000:0000:0000:0000:0000:0000:0000".len() as u8),
-            // A CIDR is only this long
-            DataValueType::CIDR => Some("0000:0000:0000:0000:0000:0000:0000:0000/128".len() as u8),
+            DataValueType::Boolean => Some("false".len()),
+            DataValueType::IP => IPAddress.maximum_bound(),
+            DataValueType::CIDR => IPNetwork.maximum_bound(),
             DataValueType::RelativeDate => None,
             DataValueType::AbsoluteDate => None,
             DataValueType::String => None,
+            DataValueType::Unquoted => None,
+            DataValueType::Wildcard => None,
         }
     }
 }
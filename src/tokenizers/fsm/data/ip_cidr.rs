@@ -8,7 +8,7 @@ use super::ipv6_cidr::{IPv6Address, IPv6Network};
 pub struct IPAddress;
 
 impl FSMStateMatcher for IPAddress {
-    fn matches(self, inp: &str) -> Option<u8> {
+    fn matches(self, inp: &str) -> Option<usize> {
         IPv4Network
             .matches(inp)
             .or_else(|| IPv4Address.matches(inp))
@@ -16,8 +16,24 @@ impl FSMStateMatcher for IPAddress {
             .or_else(|| IPv6Address.matches(inp))
     }
 
-    fn maximum_bound(self) -> Option<u8> {
-        IPv4Address::maximum_bound(IPv4Address).max(IPv4Network::maximum_bound(IPv4Network))
+    fn maximum_bound(self) -> Option<usize> {
+        IPv4Network.maximum_bound().max(IPv6Network.maximum_bound())
+    }
+}
+
+/// Matches an IPv4 or IPv6 network in CIDR notation, such as `10.0.0.0/8`
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct IPNetwork;
+
+impl FSMStateMatcher for IPNetwork {
+    fn matches(self, inp: &str) -> Option<usize> {
+        IPv4Network
+            .matches(inp)
+            .or_else(|| IPv6Network.matches(inp))
+    }
+
+    fn maximum_bound(self) -> Option<usize> {
+        IPv4Network.maximum_bound().max(IPv6Network.maximum_bound())
     }
 }
 
@@ -28,12 +44,11 @@ struct IPv4Address;
 struct IPv4Network;
 
 impl FSMStateMatcher for IPv4Network {
-    fn matches(self, inp: &str) -> Option<u8> {
-        assert!(inp.len() <= u8::MAX as usize);
+    fn matches(self, inp: &str) -> Option<usize> {
         trace!("checking if ip address prefix exists");
         let ip_prefix = IPv4Address.matches(inp)?;
         trace!("got an IP, checking for network section");
-        let network_section: &str = &inp[((ip_prefix + 1) as usize)..];
+        let network_section: &str = &inp[ip_prefix..];
         trace!("checking if there is enough characters for network section");
         if network_section.len() < 2 {
             // network section is atleast /0, so two characters are needed, if we have less, abort
@@ -72,17 +87,17 @@ impl FSMStateMatcher for IPv4Network {
         }
     }
 
-    fn maximum_bound(self) -> Option<u8> {
-        Some("255.255.255.255/32".len() as u8)
+    fn maximum_bound(self) -> Option<usize> {
+        Some("255.255.255.255/32".len())
     }
 }
 
 impl FSMStateMatcher for IPv4Address {
-    fn matches(self, inp: &str) -> Option<u8> {
-        assert!(inp.len() <= u8::MAX as usize);
+    fn matches(self, inp: &str) -> Option<usize> {
         trace!(?inp, "checking if string is ipv4");
-        let inp_bound = Self.maximum_bound().unwrap().min(inp.len() as u8);
-        let inp_bound = inp_bound as usize;
+        // an IPv4 address is plain ascii, anything past the first other character can be cut off
+        let inp_bound = Self.maximum_bound().unwrap()
+            .min(inp.find(|c: char| !c.is_ascii()).unwrap_or(inp.len()));
 
         /// checks if the given string slice is a valid u8
         fn is_byte(s: &str) -> bool {
@@ -125,10 +140,9 @@ impl FSMStateMatcher for IPv4Address {
                 if !is_byte(&inp[0..i]) {
                     return None;
                 }
-                let full_pos = partial_pos + i - 1;
-                assert!(full_pos < u8::MAX as usize);
+                let full_pos = partial_pos + i;
                 trace!(?full_pos, inp = (&inp[..i]), "complete ip located");
-                return Some(full_pos as u8);
+                return Some(full_pos);
             }
             trace!("got digit, checking next one")
         }
@@ -138,14 +152,13 @@ impl FSMStateMatcher for IPv4Address {
             trace!("terminating digit bad, aborting parse");
             return None;
         }
-        let full_pos = partial_pos + i - 1;
+        let full_pos = partial_pos + i;
         trace!(?full_pos, inp = (&inp[..i]), "complete ip located");
-        assert!(full_pos < u8::MAX as usize);
-        Some(full_pos as u8)
+        Some(full_pos)
     }
 
-    fn maximum_bound(self) -> Option<u8> {
-        Some("255.255.255.255".len() as u8)
+    fn maximum_bound(self) -> Option<usize> {
+        Some("255.255.255.255".len())
     }
 }
 
@@ -159,11 +172,11 @@ mod test {
         use super::IPv4Address as a;
         macro_rules! test {
             (exact $ip:literal) => {
-                assert_eq!(Some($ip.len() as u8 -1), a::matches(a, $ip))
+                assert_eq!(Some($ip.len()), a::matches(a, $ip))
             };
             (extra $ip:literal $extra:literal) => {
                 let ip = format!("{}{}", $ip, $extra);
-                assert_eq!(Some($ip.len() as u8 -1), a::matches(a, &ip))
+                assert_eq!(Some($ip.len()), a::matches(a, &ip))
             };
             (bad $ip:literal) => {
                 assert_eq!(None, a::matches(a, $ip))
@@ -196,12 +209,13 @@ mod test {
     #[tracing_test::traced_test]
     pub fn test_ipv4_cidr_matcher() {
         use super::IPv4Network as a;
-        assert_eq!(Some(11), a::matches(a, "127.0.0.1/12"));
-        assert_eq!(Some(12), a::matches(a, "127.0.0.127/1"));
-        assert_eq!(Some(17), a::matches(a, "255.255.255.255/32"));
-        assert_eq!(Some(8), a::matches(a, "0.0.0.0/0"));
-        assert_eq!(Some(9), a::matches(a, "0.0.0.0/00"));
+        assert_eq!(Some(12), a::matches(a, "127.0.0.1/12"));
+        assert_eq!(Some(13), a::matches(a, "127.0.0.127/1"));
+        assert_eq!(Some(18), a::matches(a, "255.255.255.255/32"));
+        assert_eq!(Some(9), a::matches(a, "0.0.0.0/0"));
+        assert_eq!(Some(10), a::matches(a, "0.0.0.0/00"));
         assert_eq!(None, a::matches(a, "127.255.255.1/99"));
+        assert_eq!(None, a::matches(a, "127.0.0.1"));
     }
 
     proptest::proptest! {
@@ -209,20 +223,16 @@ mod test {
         fn proptest_ipv4_matcher(d1 in 0..255u8, d2 in 0..255u8, d3 in 0..255u8, d4 in 0..255u8, g in "[a-zA-Z,) ][0-5]") {
             let ip_as_string = format!("{d1}.{d2}.{d3}.{d4}{g}");
             let ip_as_string_len = ip_as_string.len() - g.len();
-            // subtract one, the data we get is an index from where the string ends
-            let ip_as_string_len = ip_as_string_len - 1;
             let matcher_len = super::IPv4Address.matches(&ip_as_string).unwrap();
-            assert_eq!(ip_as_string_len, matcher_len as usize);
+            assert_eq!(ip_as_string_len, matcher_len);
         }
 
         #[test]
         fn proptest_ipv4_network_matcher(d1 in 0..255u8, d2 in 0..255u8, d3 in 0..255u8, d4 in 0..255u8, n in 0..32u8, g in "[a-zA-Z,) ][0-5]") {
             let ip_as_string = format!("{d1}.{d2}.{d3}.{d4}/{n}{g}");
             let ip_as_string_len = ip_as_string.len() - g.len();
-            // subtract one, the data we get is an index from where the string ends
-            let ip_as_string_len = ip_as_string_len - 1;
             let matcher_len = super::IPv4Network.matches(&ip_as_string).unwrap();
-            assert_eq!(ip_as_string_len, matcher_len as usize);
+            assert_eq!(ip_as_string_len, matcher_len);
         }
     }
 }
@@ -1,3 +1,7 @@
+use std::{net::Ipv6Addr, str::FromStr};
+
+use tracing::trace;
+
 use crate::tokenizers::fsm::FSMStateMatcher;
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
@@ -7,48 +11,61 @@ pub struct IPv6Address;
 pub struct IPv6Network;
 
 impl FSMStateMatcher for IPv6Address {
-    fn matches(self, inp: &str) -> Option<u8> {
-        const char_set: &'static str = "0123456789abcdefABCDEF:";
-        const max_ipv6: usize = "2001:0db8:85a3:08d3:1319:8a2e:0370:7344".len();
-        const min_ipv6: usize = "::".len();
-        if inp.len() < min_ipv6 {
+    fn matches(self, inp: &str) -> Option<usize> {
+        const CHAR_SET: &str = "0123456789abcdefABCDEF:";
+        const MIN_IPV6: usize = "::".len();
+        let candidate = candidate(inp, CHAR_SET, Self.maximum_bound()?)?;
+        if candidate.len() < MIN_IPV6 {
             return None
         }
-        for (i, c) in inp.chars().take(max_ipv6).enumerate() {
-            if char_set.contains(c) {
-                continue
-            } else {
-                if i < min_ipv6 {
-                    return None
-                }
-                return Some(i as u8 - 1u8)
-            }
-        }
-        Some(inp.len().min(0xFF) as u8)
+        trace!(?candidate, "checking if candidate is a valid ipv6 address");
+        Ipv6Addr::from_str(candidate).ok()?;
+        Some(candidate.len())
+    }
+
+    fn maximum_bound(self) -> Option<usize> {
+        Some("2001:0db8:85a3:08d3:1319:8a2e:0370:7344".len())
     }
 }
 
 impl FSMStateMatcher for IPv6Network {
-    fn matches(self, inp: &str) -> Option<u8> {
-        const char_set: &'static str = "0123456789abcdefABCDEF:/";
-        const max_ipv6: usize = "2001:0db8:85a3:08d3:1319:8a2e:0370:7344/127".len();
-        const min_ipv6: usize = "::/1".len();
-        if inp.len() < min_ipv6 {
+    fn matches(self, inp: &str) -> Option<usize> {
+        const CHAR_SET: &str = "0123456789abcdefABCDEF:/";
+        const MIN_IPV6: usize = "::/1".len();
+        let candidate = candidate(inp, CHAR_SET, Self.maximum_bound()?)?;
+        if candidate.len() < MIN_IPV6 {
             return None
         }
-        for (i, c) in inp.chars().take(max_ipv6).enumerate() {
-            if char_set.contains(c) {
-                continue
-            } else {
-                if i < min_ipv6 {
-                    return None
-                }
-                return Some(i as u8 - 1u8)
-            }
+        trace!(?candidate, "checking if candidate is a valid ipv6 network");
+        let (address, netmask) = candidate.split_once('/')?;
+        Ipv6Addr::from_str(address).ok()?;
+        let netmask: u8 = netmask.parse().ok()?;
+        if netmask > 128 {
+            trace!(?netmask, "netmask out of range");
+            return None
         }
+        Some(candidate.len())
+    }
+
+    fn maximum_bound(self) -> Option<usize> {
+        Some("2001:0db8:85a3:08d3:1319:8a2e:0370:7344/128".len())
+    }
+}
 
-        Some(inp.len().min(0xFF) as u8)
+/// Returns the longest prefix of the input made up only of characters
+/// from the given set, up to the maximum length
+fn candidate<'a>(inp: &'a str, char_set: &str, max: usize) -> Option<&'a str> {
+    let end = inp
+        .char_indices()
+        .take(max + 1)
+        .find(|(_, c)| !char_set.contains(*c))
+        .map(|(i, _)| i)
+        .unwrap_or(inp.len());
+    if end > max {
+        trace!("candidate longer than any valid ipv6");
+        return None
     }
+    Some(&inp[..end])
 }
 
 #[cfg(test)]
@@ -64,6 +81,10 @@ mod test {
         assert_eq!(Some(4), a::matches(a, "::/1"));
         assert_eq!(Some(28), a::matches(a, "2001:0db8:85a3::0370:7344/32"));
         assert_eq!(Some(12), a::matches(a, "0000::0000/0"));
+        assert_eq!(Some(13), a::matches(a, "2001:db8::/32 OR"));
+        assert_eq!(None, a::matches(a, "2001:db8::1"));
+        assert_eq!(None, a::matches(a, "2001:db8::/129"));
+        assert_eq!(None, a::matches(a, "dead:/12"));
     }
 
     #[test]
@@ -77,11 +98,11 @@ mod test {
 
         macro_rules! test {
          (exact $ip:literal) => {
-             assert_eq!(Some($ip.len() as u8), a::matches(a, $ip))
+             assert_eq!(Some($ip.len()), a::matches(a, $ip))
          };
          (extra $ip:literal $extra:literal) => {
              let ip = format!("{}{}", $ip, $extra);
-             assert_eq!(Some($ip.len() as u8), a::matches(a, &ip))
+             assert_eq!(Some($ip.len()), a::matches(a, &ip))
          };
          (bad $ip:literal) => {
              assert_eq!(None, a::matches(a, $ip))
@@ -100,9 +121,15 @@ mod test {
         test!(exact "0:0::0");
         test!(exact "0::0:0");
         test!(exact "0::0");
-        //test!(exact "::0");
+        test!(exact "::0");
         test!(exact "0::");
-        //test!(exact "::");
+        test!(exact "::");
+        test!(extra "2001:db8::1" ")");
+        test!(extra "2001:db8::1" " AND");
+        test!(bad "dead");
+        test!(bad "2023");
+        test!(bad "ab");
+        test!(bad "1:2:3:4:5:6:7:8:9");
      }
 
     prop_compose! {
@@ -137,13 +164,13 @@ mod test {
          #[test]
          fn proptest_ipv6_matcher(ip in arb_full_ipv6()) {
              let mat = IPv6Address.matches(&ip).unwrap();
-            assert_eq!(mat as usize, ip.len())
+            assert_eq!(mat, ip.len())
          }
  
          #[test]
          fn proptest_trimmed_ipv6_matcher(ip in arb_simple_trim_ipv6()) {
              let mat = IPv6Address.matches(&ip).unwrap();
-            assert_eq!(mat as usize, ip.len())
+            assert_eq!(mat, ip.len())
          }
      }
  }
@@ -0,0 +1,57 @@
+use tracing::trace;
+
+use crate::tokenizers::fsm::FSMStateMatcher;
+
+/// Matches a term surrounded by double quotes, such as `"rose (flower)"`
+///
+/// Inside the quotes, `\"` and `\\` escape a quote or backslash, any other
+/// character is taken verbatim. The returned length includes both quotes.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct QuotedTerm;
+
+impl FSMStateMatcher for QuotedTerm {
+    fn matches(self, inp: &str) -> Option<usize> {
+        let mut bytes = inp.bytes().enumerate();
+        let Some((_, b'"')) = bytes.next() else {
+            return None;
+        };
+        while let Some((pos, chr)) = bytes.next() {
+            match chr {
+                b'\\' => {
+                    // skip whatever is escaped, the unquoting resolves it later
+                    bytes.next();
+                }
+                b'"' if pos == 1 => {
+                    trace!("empty quoted term");
+                    return None;
+                }
+                b'"' => {
+                    trace!(end = pos, "found closing quote");
+                    return Some(pos + 1);
+                }
+                _ => (),
+            }
+        }
+        trace!("no closing quote found");
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tokenizers::fsm::FSMStateMatcher;
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_quoted_matcher() {
+        use super::QuotedTerm as a;
+        assert_eq!(Some(15), a::matches(a, r#""rose (flower)""#));
+        assert_eq!(Some(13), a::matches(a, r#""cat AND dog" OR bird"#));
+        assert_eq!(Some(9), a::matches(a, r#""a \"b\"""#));
+        assert_eq!(Some(6), a::matches(a, r#""a\\b","#));
+        assert_eq!(None, a::matches(a, r#""a \""#));
+        assert_eq!(None, a::matches(a, r#""unterminated"#));
+        assert_eq!(None, a::matches(a, r#""""#));
+        assert_eq!(None, a::matches(a, "rose"));
+    }
+}
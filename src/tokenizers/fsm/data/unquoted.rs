@@ -0,0 +1,83 @@
+use tracing::trace;
+
+use crate::tokenizers::fsm::{
+    dialect::{is_word, Dialect},
+    FSMStateMatcher,
+};
+
+/// Matches the unquoted value of a `field:value` search, such as `someone`
+/// in `uploader:someone`
+///
+/// The value ends at a parenthesis, the AND or OR of the dialect, the end of
+/// the line or a comment as well as a boost or fuzz following it, a backslash
+/// escapes the next character. If the dialect implies AND between terms, it
+/// ends at whitespace.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct UnquotedTerm<'d>(pub &'d Dialect);
+
+impl FSMStateMatcher for UnquotedTerm<'_> {
+    fn matches(self, inp: &str) -> Option<usize> {
+        let mut chars = inp.char_indices().peekable();
+        let mut end = inp.len();
+        while let Some((pos, chr)) = chars.next() {
+            let rest = &inp[pos..];
+            let ends = match chr {
+                '\\' => {
+                    chars.next();
+                    false
+                }
+                '(' | ')' | '"' => true,
+                '^' | '~' => rest[1..].starts_with(|c: char| c.is_ascii_digit()),
+                '\n' | '\r' => true,
+                c if c.is_whitespace() => self.0.implicit_and() || rest.trim_start().starts_with('#'),
+                // words such as `AND` only after whitespace, `ORACLE` is a value
+                _ => self.0.combinator_at(rest).is_some_and(|k| {
+                    !is_word(k) || inp[..pos].ends_with(char::is_whitespace)
+                }),
+            };
+            if ends {
+                trace!(end = pos, "found end of unquoted term");
+                end = pos;
+                break;
+            }
+        }
+        let len = inp[..end].trim_end().len();
+        if len == 0 {
+            return None;
+        }
+        Some(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UnquotedTerm;
+    use crate::tokenizers::fsm::{dialect::Dialect, FSMStateMatcher};
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_unquoted_matcher() {
+        let dialect = Dialect::default();
+        let a = UnquotedTerm(&dialect);
+        assert_eq!(Some(7), a.matches("someone"));
+        assert_eq!(Some(8), a.matches("some one AND other"));
+        assert_eq!(Some(4), a.matches("1920,tag"));
+        assert_eq!(Some(10), a.matches("3 days ago)"));
+        assert_eq!(Some(19), a.matches("https://example.com || tag"));
+        assert_eq!(Some(6), a.matches("s\\(x\\)"));
+        assert_eq!(Some(3), a.matches("foo~1"));
+        assert_eq!(Some(6), a.matches("ORACLE"));
+        assert_eq!(Some(7), a.matches("someone\nother"));
+        assert_eq!(Some(7), a.matches("someone # uploader"));
+        assert_eq!(None, a.matches(", tag"));
+        assert_eq!(None, a.matches(""));
+        let dialect = Dialect::default().with_and(&["and", "&"]).with_or(&["or"]);
+        let a = UnquotedTerm(&dialect);
+        assert_eq!(Some(4), a.matches("some and other"));
+        assert_eq!(Some(4), a.matches("some&other"));
+        assert_eq!(Some(8), a.matches("1920,tag"));
+        let dialect = Dialect::default().with_implicit_and();
+        let a = UnquotedTerm(&dialect);
+        assert_eq!(Some(4), a.matches("some one"));
+    }
+}
@@ -0,0 +1,168 @@
+//! The spelling of the operators combining and negating clauses, so a site
+//! can use lowercase `and`/`or`, `&`/`|` or keep `-` for tags such as `-_-`
+
+use std::path::Path;
+
+use crate::{errors, tokens::Token};
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Dialect {
+    /// Combines clauses that must all match, such as `AND`, `&&` or `,`
+    and: Vec<String>,
+    /// Combines clauses of which any must match, such as `OR` or `||`
+    or: Vec<String>,
+    /// Negates the clause following it, symbols such as `-` only at the start of a term
+    not: Vec<String>,
+    /// Whether keywords match regardless of their case
+    ignore_case: bool,
+    /// Whether adjacent terms are combined with AND, whitespace then ends a
+    /// term so tags with spaces must be quoted
+    implicit_and: bool,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self {
+            and: strings(&["AND", "&&", ","]),
+            or: strings(&["OR", "||"]),
+            not: strings(&["NOT", "!", "-"]),
+            ignore_case: false,
+            implicit_and: false,
+        }
+    }
+}
+
+fn strings(keywords: &[&str]) -> Vec<String> {
+    keywords.iter().map(|k| k.to_string()).collect()
+}
+
+/// Whether the keyword is a word such as `AND` rather than a symbol such as `&&`,
+/// words only match as a whole
+pub(crate) fn is_word(keyword: &str) -> bool {
+    keyword.starts_with(is_word_char)
+}
+
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Dialect {
+    pub fn with_and(mut self, keywords: &[&str]) -> Self {
+        self.and = strings(keywords);
+        self
+    }
+
+    pub fn with_or(mut self, keywords: &[&str]) -> Self {
+        self.or = strings(keywords);
+        self
+    }
+
+    pub fn with_not(mut self, keywords: &[&str]) -> Self {
+        self.not = strings(keywords);
+        self
+    }
+
+    pub fn ignoring_case(mut self) -> Self {
+        self.ignore_case = true;
+        self
+    }
+
+    pub fn with_implicit_and(mut self) -> Self {
+        self.implicit_and = true;
+        self
+    }
+
+    pub fn implicit_and(&self) -> bool {
+        self.implicit_and
+    }
+
+    pub fn from_json(inp: &str) -> errors::Result<Self> {
+        Ok(serde_json::from_str(inp)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> errors::Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// The keywords of AND, OR and NOT, the first is how the operator is usually spelled
+    pub(crate) fn keywords(&self) -> [(Token, &[String]); 3] {
+        [(Token::AND, &self.and), (Token::OR, &self.or), (Token::NOT, &self.not)]
+    }
+
+    /// The AND keyword the input starts with
+    pub(crate) fn and_at<'i>(&self, inp: &'i str) -> Option<&'i str> {
+        self.keyword(&self.and, inp)
+    }
+
+    /// The OR keyword the input starts with
+    pub(crate) fn or_at<'i>(&self, inp: &'i str) -> Option<&'i str> {
+        self.keyword(&self.or, inp)
+    }
+
+    /// The NOT keyword the input starts with
+    pub(crate) fn not_at<'i>(&self, inp: &'i str) -> Option<&'i str> {
+        self.keyword(&self.not, inp)
+    }
+
+    /// The AND or OR keyword the input starts with
+    pub(crate) fn combinator_at<'i>(&self, inp: &'i str) -> Option<&'i str> {
+        self.and_at(inp).or_else(|| self.or_at(inp))
+    }
+
+    /// Whether a keyword starts with the character, a backslash in front of it
+    /// keeps it part of a term
+    pub(crate) fn starts_keyword(&self, c: char) -> bool {
+        self.and.iter().chain(&self.or).chain(&self.not).any(|k| {
+            k.chars().next().is_some_and(|first| {
+                if self.ignore_case {
+                    first.eq_ignore_ascii_case(&c)
+                } else {
+                    first == c
+                }
+            })
+        })
+    }
+
+    /// The longest of the keywords the input starts with
+    fn keyword<'i>(&self, keywords: &[String], inp: &'i str) -> Option<&'i str> {
+        keywords
+            .iter()
+            .filter(|k| !k.is_empty())
+            .filter_map(|k| {
+                let start = inp.get(..k.len())?;
+                let same = if self.ignore_case {
+                    start.eq_ignore_ascii_case(k)
+                } else {
+                    start == k
+                };
+                // `ORACLE` does not start with `OR`
+                let whole = !is_word(k) || !inp[k.len()..].starts_with(is_word_char);
+                (same && whole).then_some(start)
+            })
+            .max_by_key(|k| k.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Dialect;
+
+    #[test]
+    pub fn test_keywords() {
+        let dialect = Dialect::default();
+        assert_eq!(Some("AND"), dialect.and_at("AND rose"));
+        assert_eq!(Some("&&"), dialect.combinator_at("&&rose"));
+        assert_eq!(Some("OR"), dialect.or_at("OR"));
+        assert_eq!(None, dialect.or_at("ORACLE"));
+        assert_eq!(None, dialect.and_at("and rose"));
+        assert_eq!(Some("-"), dialect.not_at("-_-"));
+        let dialect = Dialect::default().with_and(&["and", "&"]).with_or(&["or", "|"]).with_not(&["not"]).ignoring_case();
+        assert_eq!(Some("AnD"), dialect.and_at("AnD rose"));
+        assert_eq!(Some("&"), dialect.and_at("&&"));
+        assert_eq!(Some("|"), dialect.combinator_at("|rose"));
+        assert_eq!(None, dialect.not_at("-_-"));
+        assert!(dialect.starts_keyword('N'));
+        assert!(!dialect.starts_keyword('-'));
+    }
+}
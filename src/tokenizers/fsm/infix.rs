@@ -1,4 +1,4 @@
-use super::FSMStateMatcher;
+use super::dialect::Dialect;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InfixOperator {
@@ -6,34 +6,13 @@ pub enum InfixOperator {
     Or,
 }
 
-impl FSMStateMatcher for InfixOperator {
-    fn matches(self, inp: &str) -> Option<u8> {
+impl InfixOperator {
+    /// Returns the length of the operator as spelled in the dialect
+    pub fn matches(self, inp: &str, dialect: &Dialect) -> Option<usize> {
         match self {
-            InfixOperator::And => {
-                if inp.starts_with(',') {
-                    Some(1)
-                } else if inp.starts_with("&&") {
-                    Some(2)
-                } else if inp.starts_with("AND") {
-                    Some(3)
-                } else {
-                    None
-                }
-            }
-            InfixOperator::Or => {
-                if inp.starts_with("||") || inp.starts_with("OR") {
-                    Some(2)
-                } else {
-                    None
-                }
-            }
-        }
-    }
-
-    fn maximum_bound(self) -> Option<u8> {
-        match self {
-            InfixOperator::And => Some(3),
-            InfixOperator::Or => Some(2),
+            InfixOperator::And => dialect.and_at(inp),
+            InfixOperator::Or => dialect.or_at(inp),
         }
+        .map(str::len)
     }
 }
@@ -1,32 +1,45 @@
-use std::rc::Rc;
+use std::{ops::Range, sync::Arc};
 
 use tracing::trace;
 
-use crate::{span::TokenSpan, tokens::Token};
+use crate::{errors, schema::Schema, span::TokenSpan, tokens::Token};
 
-use self::state_machine::StateMachine;
+use self::{dialect::Dialect, infix::InfixOperator, state_machine::StateMachine};
 
 use super::{ITokenizerFactory, ITokenizer};
 
 pub mod comp;
 pub mod data;
+pub mod dialect;
 pub mod infix;
+pub mod postfix;
 pub mod prefix;
 pub mod state_machine;
 pub mod token_and_field;
 
 inventory::submit! { crate::tokenizers::Tokenizer::new::<FSMFactory>("fsm") }
 
-#[derive(Clone, Copy, Debug)]
-pub struct FSMFactory;
+#[derive(Clone, Debug, Default)]
+pub struct FSMFactory {
+    dialect: Arc<Dialect>,
+}
+
+impl FSMFactory {
+    /// Creates tokenizers recognizing the operators as spelled in the dialect
+    pub fn with_dialect(dialect: Dialect) -> Self {
+        Self {
+            dialect: Arc::new(dialect),
+        }
+    }
+}
 
 impl ITokenizerFactory for FSMFactory {
     fn init() -> Box<dyn ITokenizerFactory> {
-        Box::new(Self)
+        Box::new(Self::default())
     }
 
-    fn new(&self, input: std::rc::Rc<str>) -> Box<dyn ITokenizer> {
-        Box::new(Tokenizer::new(input))
+    fn new(&self, input: std::sync::Arc<str>, schema: Arc<Schema>) -> Box<dyn ITokenizer> {
+        Box::new(Tokenizer::new(input, schema).with_dialect(self.dialect.clone()))
     }
 }
 
@@ -41,17 +54,17 @@ pub trait FSM: Default + Copy + Clone + PartialEq + Eq + FSMStateMatcher {
 }
 
 pub trait FSMStateMatcher: Copy + Clone + PartialEq + Eq {
-    /// Returns the number of characters to consume when it matches
+    /// Returns the number of bytes to consume when it matches
     /// the current input position forward
     ///
     /// If it returns none, input does not match
-    fn matches(self, inp: &str) -> Option<u8>;
-    /// The maximum number of characters this matcher will consume.
+    fn matches(self, inp: &str) -> Option<usize>;
+    /// The maximum number of bytes this matcher will consume.
     ///
-    /// The input string will be clamped to this number of characters.
+    /// Longer matches are rejected by the tokenizer as too long.
     ///
     /// If None is returned, the match is unbounded
-    fn maximum_bound(self) -> Option<u8> {
+    fn maximum_bound(self) -> Option<usize> {
         None
     }
     /// If true is returned, the parser will skip any whitespace after a match
@@ -63,110 +76,306 @@ pub trait FSMStateMatcher: Copy + Clone + PartialEq + Eq {
 
 #[derive(Clone, Debug)]
 pub struct Tokenizer {
-    pub inp: Rc<str>,
+    pub inp: Arc<str>,
     pub position: usize,
     pub state: StateMachine,
+    pub schema: Arc<Schema>,
+    pub dialect: Arc<Dialect>,
+    /// The whitespace containing a line break skipped after the last token
+    pub line_break: Option<Range<usize>>,
+    /// Whether the [Token::ROOT] was yielded by the iterator
+    pub started: bool,
+    /// Whether the iterator reached the end of input or an error
+    pub finished: bool,
+}
+
+/// Returns the length of the whitespace and `#` comments at the start of the
+/// input and whether they contain a line break
+fn blank(inp: &str) -> (usize, bool) {
+    let mut len = 0;
+    let mut line_break = false;
+    loop {
+        let rest = &inp[len..];
+        let word = rest.trim_start();
+        line_break |= rest[..rest.len() - word.len()].contains('\n');
+        len += rest.len() - word.len();
+        if !word.starts_with('#') {
+            return (len, line_break);
+        }
+        // comments run until the end of the line
+        match word.find('\n') {
+            Some(end) => len += end,
+            None => return (inp.len(), line_break),
+        }
+    }
+}
+
+/// Returns the length of the input that cannot be scanned, up to the next
+/// whitespace, parenthesis or symbol combinator but at least its first character
+fn resync(inp: &str, dialect: &Dialect) -> usize {
+    inp.char_indices()
+        .skip(1)
+        .find(|(i, c)| {
+            c.is_whitespace()
+                || matches!(c, '(' | ')')
+                || dialect.combinator_at(&inp[*i..]).is_some_and(|k| !dialect::is_word(k))
+        })
+        .map_or(inp.len(), |(i, _)| i)
 }
 
 impl Tokenizer {
-    pub fn new(inp: Rc<str>) -> Self {
+    pub fn new(inp: Arc<str>, schema: Arc<Schema>) -> Self {
+        // the first token starts after any leading whitespace and comments
+        let (position, _) = blank(&inp);
         Self {
             inp,
-            position: 0,
+            position,
             state: StateMachine::default(),
+            schema,
+            dialect: Arc::default(),
+            line_break: None,
+            started: false,
+            finished: false,
         }
     }
+
+    /// Recognizes the operators as spelled in the dialect instead of the default one
+    pub fn with_dialect(mut self, dialect: Arc<Dialect>) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
     /// Tries to get the next token in the input or returns None if no possible
     /// token can match the remainder of the input
+    ///
+    /// A token longer than the [FSMStateMatcher::maximum_bound] of its state is an error.
     #[tracing::instrument(skip_all)]
-    pub fn step(&mut self) -> Option<TokenSpan> {
+    pub fn step(&mut self) -> errors::Result<Option<TokenSpan>> {
         // Check if we're at the end and escape hatch out
         if self.state == StateMachine::EndOfInput {
-            return None;
+            return Ok(None);
+        }
+        // a line break ends the clause unless the next line continues it
+        if let Some(line_break) = self.line_break.take() {
+            let rest = &self.inp[self.position..];
+            let next_states = self.state.next_states();
+            if next_states.contains(&StateMachine::Newline)
+                && !next_states.iter().any(|next| next.matches_with(rest, &self.dialect).is_some())
+            {
+                trace!("Line break ends the clause: {:?} -> Newline", self.state);
+                self.state = StateMachine::Newline;
+                return Ok(Some(TokenSpan::new(self.inp.clone(), line_break, Token::NEWLINE)));
+            }
         }
         for next in self.state.next_states() {
             trace!("Attempting to transition: {:?} -> {next:?}", self.state);
-            if let Some(chars) = next.matches(&self.inp[self.position..]) {
-                let mut span = TokenSpan::new(
-                    self.inp.clone(),
-                    self.position..self.position + (chars as usize),
-                    next.to_token(),
-                );
+            if let Some(chars) = next.matches_with(&self.inp[self.position..], &self.dialect) {
+                let range = self.position..self.position + chars;
+                // `name:` only starts a field search if the schema knows the field
+                if *next == StateMachine::ColonField && self.schema.field(&self.inp[range.clone()]).is_none() {
+                    trace!(" -> Not a known field, skipping transition to {next:?}");
+                    continue;
+                }
+                let mut span = TokenSpan::new(self.inp.clone(), range.clone(), next.to_token());
+                if let Some(max) = next.maximum_bound().filter(|max| chars > *max) {
+                    trace!(" -> {chars} characters exceed the maximum of {max} for {next:?}");
+                    return Err(errors::Error::LexemeTooLong(span, max));
+                }
                 if next.trailing_whitespace_trim() {
                     span.trim_end_whitespace();
                 }
                 trace!(
                     " -> Transition from {:?} to {next:?} with {chars:?} characters: {:?}",
                     self.state,
-                    &self.inp[self.position..self.position + (chars as usize)]
+                    &self.inp[range]
                 );
                 // move the input stream
-                self.position += chars as usize;
-                // forward space through input whitespace at end of token
-                let fsws = self
-                    .inp
-                    .get(self.position..)
-                    .unwrap_or_default()
-                    .chars()
-                    .take_while(|x| x.is_whitespace())
-                    .count();
+                self.position += chars;
+                // forward space through input whitespace and comments at end of token
+                let (fsws, line_break) = blank(self.inp.get(self.position..).unwrap_or_default());
                 trace!(" <- Forward Space White Space : {fsws}");
+                if line_break {
+                    self.line_break = Some(self.position..self.position + fsws);
+                }
                 self.position += fsws;
                 // update the state machine
                 self.state = *next;
-                return Some(span);
+                return Ok(Some(span));
             } else {
                 // no match, try the next one
             }
         }
+        if let Some(span) = self.implicit_and() {
+            return Ok(Some(span));
+        }
         trace!("Could not transition to EoI but could not match any transition");
         trace!("Remainder: {:?}", &self.inp[self.position..]);
-        None
+        Ok(None)
+    }
+
+    /// Returns an empty [Token::AND] in front of a term directly following the
+    /// previous one if the dialect implies AND between them
+    fn implicit_and(&mut self) -> Option<TokenSpan> {
+        let and = StateMachine::InfixOperator(InfixOperator::And);
+        let rest = &self.inp[self.position..];
+        if !self.dialect.implicit_and()
+            || !self.state.next_states().contains(&and)
+            || !and.next_states().iter().any(|next| next.matches_with(rest, &self.dialect).is_some())
+        {
+            return None;
+        }
+        trace!("Implicit AND: {:?} -> {and:?}", self.state);
+        self.state = and;
+        Some(TokenSpan::new(self.inp.clone(), self.position..self.position, Token::AND))
+    }
+
+    /// Skips the input the tokenizer is stuck at, up to the end of `range` or the next
+    /// point the scan can resume at, and returns the [Token::ERROR] covering it
+    ///
+    /// Returns None at the end of the input.
+    fn recover(&mut self, range: Option<Range<usize>>) -> Option<TokenSpan> {
+        let rest = &self.inp[self.position..];
+        if rest.is_empty() {
+            return None;
+        }
+        let len = range.map_or_else(|| resync(rest, &self.dialect), |r| r.end - self.position);
+        let span = TokenSpan::new(self.inp.clone(), self.position..self.position + len, Token::ERROR);
+        trace!("Recovering from {:?} with {:?}", self.state, span.str());
+        self.position += len;
+        let (fsws, line_break) = blank(&self.inp[self.position..]);
+        if line_break {
+            self.line_break = Some(self.position..self.position + fsws);
+        }
+        self.position += fsws;
+        // resume as if the error was a term unless the current state can continue
+        let rest = &self.inp[self.position..];
+        if !self.state.next_states().iter().any(|next| next.matches_with(rest, &self.dialect).is_some()) {
+            self.state = StateMachine::Tag;
+        }
+        Some(span)
+    }
+
+    /// Like [Tokenizer::scan_until_none] but input that cannot be scanned is
+    /// covered by [Token::ERROR] tokens and the scan resumes after it, so the
+    /// tokens always reach the end of input
+    #[tracing::instrument(skip(self))]
+    pub fn scan_recovering(&mut self) -> (Vec<TokenSpan>, Vec<errors::Error>) {
+        let mut out = vec![TokenSpan::new(self.inp.clone(), 0..self.inp.len(), Token::ROOT)];
+        let mut errors = Vec::new();
+        loop {
+            // the tokens expected where the scan got stuck, unless an error of its own stopped it
+            let (range, expected) = match self.step() {
+                Ok(Some(token_span)) => {
+                    out.push(token_span);
+                    continue;
+                }
+                Ok(None) if self.state == StateMachine::EndOfInput => break,
+                Ok(None) => (None, Some(self.expected())),
+                Err(e) => {
+                    let range = match &e {
+                        errors::Error::LexemeTooLong(t, _) => Some(t.range()),
+                        _ => None,
+                    };
+                    errors.push(e);
+                    (range, None)
+                }
+            };
+            let recovered = self.recover(range);
+            let end = TokenSpan::new(self.inp.clone(), self.inp.len()..self.inp.len(), Token::EOI);
+            if let Some(expected) = expected {
+                // the error covers the input skipped, or the end of the input
+                errors.push(errors::Error::ExpectedDifferentTokens(expected, recovered.clone().unwrap_or_else(|| end.clone())));
+            }
+            match recovered {
+                Some(token_span) => out.push(token_span),
+                None => {
+                    out.push(end);
+                    break;
+                }
+            }
+        }
+        (out, errors)
+    }
+
+    /// The tokens of the next states
+    fn expected(&self) -> Vec<Token> {
+        self.state.next_states().iter()
+            .map(|x| x.to_token())
+            .collect()
+    }
+
+    /// The error for input none of the next states match
+    fn unexpected(&self) -> errors::Error {
+        errors::Error::ExpectedDifferentTokens(
+            self.expected(),
+            TokenSpan::new(self.inp.clone(), self.position..self.inp.len(), Token::EOI)
+        )
     }
 
     #[tracing::instrument(skip(self))]
     pub fn scan_until_none<'bump>(
         &mut self,
-    ) -> Vec<TokenSpan>
+    ) -> errors::Result<Vec<TokenSpan>>
     {
         let mut out = Vec::new();
 
         // insert the start state token
         out.push(TokenSpan::new(self.inp.clone(), 0..self.inp.len(), Token::ROOT));
 
-        while let Some(token_span) = self.step() {
+        while let Some(token_span) = self.step()? {
             out.push(token_span)
         }
 
-        out
+        Ok(out)
     }
 }
 
 impl ITokenizer for Tokenizer {
-    fn new(input: Rc<str>) -> Box<dyn ITokenizer> {
-        Box::new(Tokenizer::new(input))
+    fn new(input: Arc<str>, schema: Arc<Schema>) -> Box<dyn ITokenizer> {
+        Box::new(Tokenizer::new(input, schema))
     }
 
-    fn token_spans(
-        &mut self,
-    ) -> Result<Vec<TokenSpan>, crate::errors::Error> {
-        let res = self.scan_until_none();
-        if self.state != StateMachine::EndOfInput {
-            Err(crate::errors::Error::ExpectedDifferentTokens(
-                self.state.next_states().into_iter()
-                    .map(|x| x.to_token())
-                    .collect(),
-                TokenSpan::new(self.inp.clone(), self.position..self.inp.len(), Token::EOI)
-            ))
-        } else {
-            Ok(res)
+    fn schema(&self) -> Arc<Schema> {
+        self.schema.clone()
+    }
+
+    fn spans(&mut self) -> Box<dyn Iterator<Item = errors::Result<TokenSpan>> + Send + '_> {
+        Box::new(self)
+    }
+
+    fn recovering_token_spans(&mut self) -> (Vec<TokenSpan>, Vec<errors::Error>) {
+        self.scan_recovering()
+    }
+}
+
+/// Yields the [Token::ROOT] followed by the tokens of the input
+///
+/// Input that cannot be scanned up to the end is an error.
+impl Iterator for Tokenizer {
+    type Item = errors::Result<TokenSpan>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        if !self.started {
+            self.started = true;
+            return Some(Ok(TokenSpan::new(self.inp.clone(), 0..self.inp.len(), Token::ROOT)));
+        }
+        let next = self.step().transpose();
+        if !matches!(next, Some(Ok(_))) {
+            self.finished = true;
+        }
+        match next {
+            None if self.state != StateMachine::EndOfInput => Some(Err(self.unexpected())),
+            next => next,
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::rc::Rc;
+    use std::sync::Arc;
 
     use crate::{span::TokenSpan, tokens::Token, tokenizers::tokenspan_to_token};
 
@@ -176,8 +385,8 @@ mod test {
     #[test]
     #[tracing_test::traced_test]
     pub fn test_empty() {
-        let input: Rc<str> = Rc::from("");
-        let token_spans = Tokenizer::new(input.clone()).scan_until_none();
+        let input: Arc<str> = Arc::from("");
+        let token_spans = Tokenizer::new(input.clone(), Arc::default()).scan_until_none().unwrap();
         let tokens = tokenspan_to_token(&token_spans);
 
         assert_eq!(
@@ -199,8 +408,8 @@ mod test {
     #[test]
     #[tracing_test::traced_test]
     pub fn test_tag() {
-        let input: Rc<str> = Rc::from("hello");
-        let token_spans = Tokenizer::new(input.clone()).scan_until_none();
+        let input: Arc<str> = Arc::from("hello");
+        let token_spans = Tokenizer::new(input.clone(), Arc::default()).scan_until_none().unwrap();
         let tokens = tokenspan_to_token(&token_spans);
 
         assert_eq!(
@@ -223,8 +432,8 @@ mod test {
     #[test]
     #[tracing_test::traced_test]
     pub fn test_field() {
-        let input: Rc<str> = Rc::from("hello.gte:10");
-        let token_spans = Tokenizer::new(input.clone()).scan_until_none();
+        let input: Arc<str> = Arc::from("hello.gte:10");
+        let token_spans = Tokenizer::new(input.clone(), Arc::default()).scan_until_none().unwrap();
         let tokens = tokenspan_to_token(&token_spans);
 
         assert_eq!(
@@ -255,8 +464,8 @@ mod test {
     #[test]
     #[tracing_test::traced_test]
     pub fn test_complex_expr() {
-        let input: Rc<str> = Rc::from("(((field.gte:1000)AND data.neq:20)||bla.gte:100.2,tag),test.lte:-10,tag");
-        let token_spans = Tokenizer::new(input.clone()).scan_until_none();
+        let input: Arc<str> = Arc::from("(((field.gte:1000)AND data.neq:20)||bla.gte:100.2,tag),test.lte:-10,tag");
+        let token_spans = Tokenizer::new(input.clone(), Arc::default()).scan_until_none().unwrap();
         trace!("{token_spans:#?}");
         let tokens = tokenspan_to_token(&token_spans);
 
@@ -293,4 +502,301 @@ mod test {
             tokens
         );
     }
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_quoted_term() {
+        let input: Arc<str> = Arc::from(r#""rose (flower)" OR description.has:"a \"b\"""#);
+        let token_spans = Tokenizer::new(input.clone(), Arc::default()).scan_until_none().unwrap();
+        let tokens = tokenspan_to_token(&token_spans);
+
+        assert_eq!(
+            vec![
+              Token::ROOT,
+              Token::QUOTED_TERM,
+              Token::OR,
+              Token::FIELD,
+              Token::RANGE,
+              Token::QUOTED_TERM,
+              Token::EOI
+            ],
+            tokens
+        );
+
+        assert_eq!(
+            vec![
+                TokenSpan::new(input.clone(), 0..44, Token::ROOT),
+                TokenSpan::new(input.clone(), 0..15, Token::QUOTED_TERM),
+                TokenSpan::new(input.clone(), 16..18, Token::OR),
+                TokenSpan::new(input.clone(), 19..31, Token::FIELD),
+                TokenSpan::new(input.clone(), 31..35, Token::RANGE),
+                TokenSpan::new(input.clone(), 35..44, Token::QUOTED_TERM),
+                TokenSpan::new(input.clone(), 44..44, Token::EOI),
+            ],
+            token_spans,
+            "Token Span positions correct"
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_ip_cidr() {
+        let input: Arc<str> = Arc::from("ip.has:10.0.0.0/8 OR ip.eq:2001:db8::1");
+        let token_spans = Tokenizer::new(input.clone(), Arc::default()).scan_until_none().unwrap();
+
+        assert_eq!(
+            vec![
+                TokenSpan::new(input.clone(), 0..38, Token::ROOT),
+                TokenSpan::new(input.clone(), 0..3, Token::FIELD),
+                TokenSpan::new(input.clone(), 3..7, Token::RANGE),
+                TokenSpan::new(input.clone(), 7..17, Token::IP_CIDR),
+                TokenSpan::new(input.clone(), 18..20, Token::OR),
+                TokenSpan::new(input.clone(), 21..24, Token::FIELD),
+                TokenSpan::new(input.clone(), 24..27, Token::RANGE),
+                TokenSpan::new(input.clone(), 27..38, Token::IP_CIDR),
+                TokenSpan::new(input.clone(), 38..38, Token::EOI),
+            ],
+            token_spans,
+            "Token Span positions correct"
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_boost_fuzz() {
+        let input: Arc<str> = Arc::from("^2 rose AND tulip~0.8^3");
+        let token_spans = Tokenizer::new(input.clone(), Arc::default()).scan_until_none().unwrap();
+
+        assert_eq!(
+            vec![
+                TokenSpan::new(input.clone(), 0..23, Token::ROOT),
+                TokenSpan::new(input.clone(), 0..2, Token::BOOST),
+                TokenSpan::new(input.clone(), 3..7, Token::TAG),
+                TokenSpan::new(input.clone(), 8..11, Token::AND),
+                TokenSpan::new(input.clone(), 12..17, Token::TAG),
+                TokenSpan::new(input.clone(), 17..21, Token::FUZZ),
+                TokenSpan::new(input.clone(), 21..23, Token::BOOST),
+                TokenSpan::new(input.clone(), 23..23, Token::EOI),
+            ],
+            token_spans,
+            "Token Span positions correct"
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_boolean() {
+        // a value only starting with a boolean is a term
+        for value in ["nobody", "yesterday", "trueman"] {
+            for field in ["uploader", "description", "x"] {
+                let input: Arc<str> = Arc::from(format!("{field}.eq:{value}"));
+                let tokens = tokenspan_to_token(&Tokenizer::new(input.clone(), Arc::default()).scan_until_none().unwrap());
+                assert_eq!(vec![Token::ROOT, Token::FIELD, Token::RANGE, Token::UNQUOTED_TERM, Token::EOI], tokens, "{input}");
+            }
+        }
+        for input in ["x.eq:yes", "x.eq:No,tag", "x.neq:TRUE)", "x.eq:false^2", "x.eq:true || tag"] {
+            let tokens = tokenspan_to_token(&Tokenizer::new(Arc::from(input), Arc::default()).scan_until_none().unwrap_or_default());
+            assert_eq!(Some(&Token::BOOLEAN), tokens.get(3), "{input}");
+        }
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_colon_field() {
+        let input: Arc<str> = Arc::from("uploader:some one,artist:foo");
+        let token_spans = Tokenizer::new(input.clone(), Arc::default()).scan_until_none().unwrap();
+
+        assert_eq!(
+            vec![
+                TokenSpan::new(input.clone(), 0..28, Token::ROOT),
+                TokenSpan::new(input.clone(), 0..8, Token::FIELD),
+                TokenSpan::new(input.clone(), 8..9, Token::RANGE),
+                TokenSpan::new(input.clone(), 9..17, Token::UNQUOTED_TERM),
+                TokenSpan::new(input.clone(), 17..18, Token::AND),
+                TokenSpan::new(input.clone(), 18..28, Token::TAG),
+                TokenSpan::new(input.clone(), 28..28, Token::EOI),
+            ],
+            token_spans,
+            "Token Span positions correct"
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_lines() {
+        let input: Arc<str> = Arc::from("# saved\nrose # red\n\ntulip AND\n  cute\n|| tag\n");
+        let token_spans = Tokenizer::new(input.clone(), Arc::default()).scan_until_none().unwrap();
+
+        assert_eq!(
+            vec![
+                TokenSpan::new(input.clone(), 0..44, Token::ROOT),
+                TokenSpan::new(input.clone(), 8..12, Token::TAG),
+                TokenSpan::new(input.clone(), 12..20, Token::NEWLINE),
+                TokenSpan::new(input.clone(), 20..25, Token::TAG),
+                TokenSpan::new(input.clone(), 26..29, Token::AND),
+                TokenSpan::new(input.clone(), 32..36, Token::TAG),
+                TokenSpan::new(input.clone(), 37..39, Token::OR),
+                TokenSpan::new(input.clone(), 40..43, Token::TAG),
+                TokenSpan::new(input.clone(), 44..44, Token::EOI),
+            ],
+            token_spans,
+            "Token Span positions correct"
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_long_lexemes() {
+        let term = format!("\"{}\"", "a".repeat(300));
+        let number = "1".repeat(300);
+        let input: Arc<str> = Arc::from(format!("{term} OR score.gt:{number}"));
+        let token_spans = Tokenizer::new(input.clone(), Arc::default()).scan_until_none().unwrap();
+
+        assert_eq!(
+            vec![
+                TokenSpan::new(input.clone(), 0..615, Token::ROOT),
+                TokenSpan::new(input.clone(), 0..302, Token::QUOTED_TERM),
+                TokenSpan::new(input.clone(), 303..305, Token::OR),
+                TokenSpan::new(input.clone(), 306..312, Token::FIELD),
+                TokenSpan::new(input.clone(), 312..315, Token::RANGE),
+                TokenSpan::new(input.clone(), 315..615, Token::INTEGER),
+                TokenSpan::new(input.clone(), 615..615, Token::EOI),
+            ],
+            token_spans,
+            "Token Span positions correct"
+        );
+
+        let input: Arc<str> = Arc::from(format!("cute AND {}", "a".repeat(256)));
+        let err = Tokenizer::new(input.clone(), Arc::default()).scan_until_none().unwrap_err();
+        let crate::errors::Error::LexemeTooLong(span, 255) = err else {
+            panic!("expected a too long tag, got {err:?}");
+        };
+        assert_eq!(TokenSpan::new(input.clone(), 9..265, Token::TAG), span);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_iterator() {
+        let input: Arc<str> = Arc::from("cute AND");
+        let mut tokenizer = Tokenizer::new(input.clone(), Arc::default());
+
+        assert_eq!(Some(TokenSpan::new(input.clone(), 0..8, Token::ROOT)), tokenizer.next().transpose().unwrap());
+        assert_eq!(Some(TokenSpan::new(input.clone(), 0..4, Token::TAG)), tokenizer.next().transpose().unwrap());
+        assert_eq!(Some(TokenSpan::new(input.clone(), 5..8, Token::AND)), tokenizer.next().transpose().unwrap());
+        assert!(matches!(
+            tokenizer.next(),
+            Some(Err(crate::errors::Error::ExpectedDifferentTokens(_, t))) if t.range() == (8..8)
+        ));
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_recovering() {
+        let input: Arc<str> = Arc::from("cute AND ) rose,width.gtee:10 || safe AND");
+        let (token_spans, errors) = Tokenizer::new(input.clone(), Arc::default()).scan_recovering();
+
+        assert_eq!(
+            vec![
+                TokenSpan::new(input.clone(), 0..41, Token::ROOT),
+                TokenSpan::new(input.clone(), 0..4, Token::TAG),
+                TokenSpan::new(input.clone(), 5..8, Token::AND),
+                TokenSpan::new(input.clone(), 9..10, Token::ERROR),
+                TokenSpan::new(input.clone(), 11..15, Token::TAG),
+                TokenSpan::new(input.clone(), 15..16, Token::AND),
+                TokenSpan::new(input.clone(), 16..22, Token::FIELD),
+                TokenSpan::new(input.clone(), 22..29, Token::ERROR),
+                TokenSpan::new(input.clone(), 30..32, Token::OR),
+                TokenSpan::new(input.clone(), 33..37, Token::TAG),
+                TokenSpan::new(input.clone(), 38..41, Token::AND),
+                TokenSpan::new(input.clone(), 41..41, Token::EOI),
+            ],
+            token_spans,
+            "Token Span positions correct"
+        );
+        // each error covers the input its ERROR token skips
+        let spans: Vec<_> = errors.iter().map(|e| match e {
+            crate::errors::Error::ExpectedDifferentTokens(_, t) => t.clone(),
+            e => panic!("unexpected error {e:?}"),
+        }).collect();
+        assert_eq!(
+            vec![
+                TokenSpan::new(input.clone(), 9..10, Token::ERROR),
+                TokenSpan::new(input.clone(), 22..29, Token::ERROR),
+                TokenSpan::new(input.clone(), 41..41, Token::EOI),
+            ],
+            spans,
+            "Error Span positions correct"
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_dialect() {
+        let dialect = Arc::new(super::Dialect::default().with_and(&["and", "&"]).with_or(&["or", "|"]).with_not(&["not"]).ignoring_case());
+        let scan = |input: &str| {
+            let tokenizer = Tokenizer::new(Arc::from(input), Arc::default()).with_dialect(dialect.clone());
+            tokenizer.map(|t| t.map(|t| (t.token(), t.str().to_string()))).collect::<crate::errors::Result<Vec<_>>>()
+        };
+
+        let tokens = scan("-_- and (rose|Not tulip) OR width.GtE:10&BRAND").unwrap();
+        assert_eq!(
+            vec![
+                (Token::TAG, "-_-"),
+                (Token::AND, "and"),
+                (Token::LPAREN, "("),
+                (Token::TAG, "rose"),
+                (Token::OR, "|"),
+                (Token::NOT, "Not"),
+                (Token::TAG, "tulip"),
+                (Token::RPAREN, ")"),
+                (Token::OR, "OR"),
+                (Token::FIELD, "width."),
+                (Token::RANGE, "GtE:"),
+                (Token::INTEGER, "10"),
+                (Token::AND, "&"),
+                (Token::TAG, "BRAND"),
+                (Token::EOI, ""),
+            ],
+            tokens[1..].iter().map(|(t, s)| (*t, s.as_str())).collect::<Vec<_>>()
+        );
+        // a comma is part of the tag when it does not combine clauses
+        assert_eq!((Token::TAG, "rose,tulip".to_string()), scan("rose,tulip").unwrap()[1]);
+        // the default dialect still negates with `-`
+        let tokens = Tokenizer::new(Arc::from("-_-"), Arc::default()).scan_until_none().unwrap();
+        assert_eq!(vec![Token::ROOT, Token::NOT, Token::TAG, Token::EOI], tokenspan_to_token(&tokens));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    pub fn test_implicit_and() {
+        let input: Arc<str> = Arc::from("red rose -\"cat tree\"\n(tulip)width.gt:3 OR safe");
+        let dialect = Arc::new(super::Dialect::default().with_implicit_and());
+        let token_spans = Tokenizer::new(input.clone(), Arc::default()).with_dialect(dialect).scan_until_none().unwrap();
+
+        assert_eq!(
+            vec![
+                TokenSpan::new(input.clone(), 0..46, Token::ROOT),
+                TokenSpan::new(input.clone(), 0..3, Token::TAG),
+                TokenSpan::new(input.clone(), 4..4, Token::AND),
+                TokenSpan::new(input.clone(), 4..8, Token::TAG),
+                TokenSpan::new(input.clone(), 9..9, Token::AND),
+                TokenSpan::new(input.clone(), 9..10, Token::NOT),
+                TokenSpan::new(input.clone(), 10..20, Token::QUOTED_TERM),
+                TokenSpan::new(input.clone(), 20..21, Token::NEWLINE),
+                TokenSpan::new(input.clone(), 21..22, Token::LPAREN),
+                TokenSpan::new(input.clone(), 22..27, Token::TAG),
+                TokenSpan::new(input.clone(), 27..28, Token::RPAREN),
+                TokenSpan::new(input.clone(), 28..28, Token::AND),
+                TokenSpan::new(input.clone(), 28..34, Token::FIELD),
+                TokenSpan::new(input.clone(), 34..37, Token::RANGE),
+                TokenSpan::new(input.clone(), 37..38, Token::INTEGER),
+                TokenSpan::new(input.clone(), 39..41, Token::OR),
+                TokenSpan::new(input.clone(), 42..46, Token::TAG),
+                TokenSpan::new(input.clone(), 46..46, Token::EOI),
+            ],
+            token_spans,
+            "Token Span positions correct"
+        );
+    }
 }
@@ -0,0 +1,21 @@
+use super::{prefix::with_argument, FSMStateMatcher};
+
+/// Lucene-style operators following their operand, such as `tag^2` or `tag~1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostfixOperator {
+    Boost,
+    Fuzz,
+}
+
+impl FSMStateMatcher for PostfixOperator {
+    fn matches(self, inp: &str) -> Option<usize> {
+        match self {
+            PostfixOperator::Boost => with_argument(inp, '^'),
+            PostfixOperator::Fuzz => with_argument(inp, '~'),
+        }
+    }
+
+    fn maximum_bound(self) -> Option<usize> {
+        None
+    }
+}
@@ -1,4 +1,4 @@
-use super::FSMStateMatcher;
+use super::dialect::Dialect;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PrefixOperator {
@@ -7,40 +7,44 @@ pub enum PrefixOperator {
     Fuzz,
 }
 
-impl FSMStateMatcher for PrefixOperator {
-    fn matches(self, inp: &str) -> Option<u8> {
+impl PrefixOperator {
+    /// Returns the length of the operator, NOT as spelled in the dialect
+    pub fn matches(self, inp: &str, dialect: &Dialect) -> Option<usize> {
         match self {
-            PrefixOperator::Not => {
-                if inp.starts_with("NOT") {
-                    Some(3)
-                } else if inp.starts_with('!') || inp.starts_with('-') {
-                    Some(1)
-                } else {
-                    None
-                }
-            }
-            PrefixOperator::Boost => {
-                if inp.starts_with('^') {
-                    Some(1)
-                } else {
-                    None
-                }
-            }
-            PrefixOperator::Fuzz => {
-                if inp.starts_with('~') {
-                    Some(1)
-                } else {
-                    None
-                }
-            }
+            PrefixOperator::Not => dialect.not_at(inp).map(str::len),
+            PrefixOperator::Boost => with_argument(inp, '^'),
+            PrefixOperator::Fuzz => with_argument(inp, '~'),
         }
     }
+}
+
+/// Matches the operator character followed by its numeric argument,
+/// such as `^2` or `~0.8`, and returns the length of both
+pub(super) fn with_argument(inp: &str, op: char) -> Option<usize> {
+    let digits = |x: &str| x.bytes().take_while(u8::is_ascii_digit).count();
+    let arg = inp.strip_prefix(op)?;
+    let int = digits(arg);
+    if int == 0 {
+        return None;
+    }
+    let frac = match arg[int..].strip_prefix('.').map(digits) {
+        Some(0) | None => 0,
+        Some(n) => n + 1,
+    };
+    Some(op.len_utf8() + int + frac)
+}
+
+#[cfg(test)]
+mod test {
+    use super::with_argument;
 
-    fn maximum_bound(self) -> Option<u8> {
-        Some(match self {
-            PrefixOperator::Not => "NOT".len(),
-            PrefixOperator::Boost => "^".len(),
-            PrefixOperator::Fuzz => "~".len(),
-        } as u8)
+    #[test]
+    pub fn test_operator_argument() {
+        assert_eq!(Some(2), with_argument("^2 tag", '^'));
+        assert_eq!(Some(4), with_argument("~0.8", '~'));
+        assert_eq!(Some(3), with_argument("~10.", '~'));
+        assert_eq!(None, with_argument("^tag", '^'));
+        assert_eq!(None, with_argument("^.5", '^'));
+        assert_eq!(None, with_argument("~2", '^'));
     }
 }
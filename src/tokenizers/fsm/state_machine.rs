@@ -3,8 +3,8 @@ use tracing::trace;
 use crate::tokens::Token;
 
 use super::{
-    comp::Comparator, data::DataValueType, infix::InfixOperator, prefix::PrefixOperator,
-    FSMStateMatcher, FSM, token_and_field,
+    comp::Comparator, data::DataValueType, dialect::Dialect, infix::InfixOperator, postfix::PostfixOperator,
+    prefix::PrefixOperator, FSMStateMatcher, FSM, token_and_field,
 };
 
 macro_rules! states {
@@ -33,12 +33,21 @@ pub enum StateMachine {
     Comparator(Comparator),
     /// A prefix operator such as NOT, BOOST or FUZZ
     PrefixOperator(PrefixOperator),
+    /// A BOOST or FUZZ following its operand
+    PostfixOperator(PostfixOperator),
     /// An IP, Date (relative or Absolute), Integer of
     DataValue(DataValueType),
     /// A field ends with a "." followed by a comparator
     Field,
+    /// A known field followed by a ":" and its value
+    ColonField,
     /// A tag is a string occuring on the element
     Tag,
+    /// A tag containing `*` or `?`, matching any tag of that pattern
+    Wildcard,
+    /// A line break between two clauses, only entered by the tokenizer
+    /// when the next line does not continue the current clause
+    Newline,
     /// State matches only end of input
     EndOfInput,
 }
@@ -60,9 +69,12 @@ impl FSM for StateMachine {
             StateMachine::Comparator(Comparator::LessThan) => Token::RANGE,
             StateMachine::Comparator(Comparator::LessThanOrEqual) => Token::RANGE,
             StateMachine::Comparator(Comparator::NotEqual) => Token::RANGE,
+            StateMachine::Comparator(Comparator::Colon) => Token::RANGE,
             StateMachine::PrefixOperator(PrefixOperator::Boost) => Token::BOOST,
             StateMachine::PrefixOperator(PrefixOperator::Fuzz) => Token::FUZZ,
             StateMachine::PrefixOperator(PrefixOperator::Not) => Token::NOT,
+            StateMachine::PostfixOperator(PostfixOperator::Boost) => Token::BOOST,
+            StateMachine::PostfixOperator(PostfixOperator::Fuzz) => Token::FUZZ,
             StateMachine::DataValue(DataValueType::AbsoluteDate) => Token::ABSOLUTE_DATE,
             StateMachine::DataValue(DataValueType::Boolean) => Token::BOOLEAN,
             StateMachine::DataValue(DataValueType::CIDR) => Token::IP_CIDR,
@@ -71,56 +83,74 @@ impl FSM for StateMachine {
             StateMachine::DataValue(DataValueType::Integer) => Token::INTEGER,
             StateMachine::DataValue(DataValueType::RelativeDate) => Token::RELATIVE_DATE,
             StateMachine::DataValue(DataValueType::String) => Token::QUOTED_TERM,
-            StateMachine::Field => Token::FIELD,
+            StateMachine::DataValue(DataValueType::Unquoted) => Token::UNQUOTED_TERM,
+            StateMachine::DataValue(DataValueType::Wildcard) => Token::WILDCARD,
+            StateMachine::Field | StateMachine::ColonField => Token::FIELD,
             StateMachine::Tag => Token::TAG,
+            StateMachine::Wildcard => Token::WILDCARD,
+            StateMachine::Newline => Token::NEWLINE,
             StateMachine::EndOfInput => Token::EOI,
         }
     }
 
     fn next_states(self) -> &'static [Self::NextStateType] {
         match self {
-            StateMachine::Start => states!(StateMachine : 7,
+            StateMachine::Start => states!(StateMachine : 10,
                 StateMachine::GroupStart,
+                StateMachine::ColonField,
                 StateMachine::Field,
+                StateMachine::Wildcard,
                 StateMachine::Tag,
+                StateMachine::DataValue(DataValueType::String),
                 StateMachine::PrefixOperator(PrefixOperator::Boost),
                 StateMachine::PrefixOperator(PrefixOperator::Fuzz),
                 StateMachine::PrefixOperator(PrefixOperator::Not),
                 StateMachine::EndOfInput,
             ),
-            StateMachine::GroupStart => states!(StateMachine : 7,
+            StateMachine::GroupStart => states!(StateMachine : 10,
                 StateMachine::GroupStart,
+                StateMachine::ColonField,
                 StateMachine::Field,
+                StateMachine::Wildcard,
                 StateMachine::Tag,
+                StateMachine::DataValue(DataValueType::String),
                 StateMachine::PrefixOperator(PrefixOperator::Boost),
                 StateMachine::PrefixOperator(PrefixOperator::Fuzz),
                 StateMachine::PrefixOperator(PrefixOperator::Not),
                 StateMachine::GroupEnd,
             ),
-            StateMachine::GroupEnd => states!(StateMachine : 4,
+            StateMachine::GroupEnd => states!(StateMachine : 7,
                 StateMachine::InfixOperator(InfixOperator::And),
                 StateMachine::InfixOperator(InfixOperator::Or),
+                StateMachine::PostfixOperator(PostfixOperator::Boost),
+                StateMachine::PostfixOperator(PostfixOperator::Fuzz),
                 StateMachine::GroupEnd,
                 StateMachine::EndOfInput,
+                StateMachine::Newline,
             ),
-            StateMachine::InfixOperator(_) => states!(StateMachine : 6,
+            StateMachine::InfixOperator(_) | StateMachine::Newline => states!(StateMachine : 9,
                 StateMachine::GroupStart,
+                StateMachine::ColonField,
                 StateMachine::Field,
+                StateMachine::Wildcard,
                 StateMachine::Tag,
+                StateMachine::DataValue(DataValueType::String),
                 StateMachine::PrefixOperator(PrefixOperator::Boost),
                 StateMachine::PrefixOperator(PrefixOperator::Fuzz),
                 StateMachine::PrefixOperator(PrefixOperator::Not),
             ),
             StateMachine::Comparator(Comparator::Equal | Comparator::NotEqual) => {
-                states!(StateMachine : 8,
+                states!(StateMachine : 9,
+                    StateMachine::DataValue(DataValueType::CIDR),
+                    StateMachine::DataValue(DataValueType::IP),
+                    StateMachine::DataValue(DataValueType::RelativeDate),
                     StateMachine::DataValue(DataValueType::AbsoluteDate),
                     StateMachine::DataValue(DataValueType::Boolean),
-                    StateMachine::DataValue(DataValueType::CIDR),
                     StateMachine::DataValue(DataValueType::Float),
-                    StateMachine::DataValue(DataValueType::IP),
                     StateMachine::DataValue(DataValueType::Integer),
-                    StateMachine::DataValue(DataValueType::RelativeDate),
                     StateMachine::DataValue(DataValueType::String),
+                    // anything else is typed against the schema by the parser
+                    StateMachine::DataValue(DataValueType::Unquoted),
                 )
             }
             StateMachine::Comparator(
@@ -128,36 +158,55 @@ impl FSM for StateMachine {
                 | Comparator::GreaterThanOrEqual
                 | Comparator::LessThan
                 | Comparator::LessThanOrEqual,
-            ) => states!(StateMachine : 4,
+            ) => states!(StateMachine : 7,
+                StateMachine::DataValue(DataValueType::CIDR),
+                StateMachine::DataValue(DataValueType::IP),
+                StateMachine::DataValue(DataValueType::RelativeDate),
                 StateMachine::DataValue(DataValueType::Float),
                 StateMachine::DataValue(DataValueType::Integer),
-                StateMachine::DataValue(DataValueType::RelativeDate),
                 StateMachine::DataValue(DataValueType::AbsoluteDate),
+                StateMachine::DataValue(DataValueType::Unquoted),
+            ),
+            StateMachine::Comparator(Comparator::Colon) => states!(StateMachine : 3,
+                StateMachine::DataValue(DataValueType::String),
+                StateMachine::DataValue(DataValueType::Wildcard),
+                StateMachine::DataValue(DataValueType::Unquoted),
             ),
-            StateMachine::Comparator(Comparator::Contains) => states!(StateMachine : 3,
+            StateMachine::Comparator(Comparator::Contains) => states!(StateMachine : 5,
                 StateMachine::DataValue(DataValueType::String),
+                StateMachine::DataValue(DataValueType::Wildcard),
                 StateMachine::DataValue(DataValueType::CIDR),
                 StateMachine::DataValue(DataValueType::IP),
+                StateMachine::DataValue(DataValueType::Unquoted),
             ),
-            StateMachine::PrefixOperator(PrefixOperator::Not) => states!(StateMachine : 5,
+            StateMachine::PrefixOperator(PrefixOperator::Not) => states!(StateMachine : 8,
                 StateMachine::GroupStart,
+                StateMachine::ColonField,
+                StateMachine::Wildcard,
                 StateMachine::Tag,
                 StateMachine::Field,
+                StateMachine::DataValue(DataValueType::String),
                 StateMachine::DataValue(DataValueType::CIDR),
                 StateMachine::DataValue(DataValueType::Boolean),
             ),
             StateMachine::PrefixOperator(PrefixOperator::Boost | PrefixOperator::Fuzz) => {
-                states!(StateMachine : 3,
+                states!(StateMachine : 6,
                     StateMachine::GroupStart,
+                    StateMachine::ColonField,
+                    StateMachine::Wildcard,
                     StateMachine::Tag,
                     StateMachine::Field,
+                    StateMachine::DataValue(DataValueType::String),
                 )
             }
-            StateMachine::DataValue(_) => states!(StateMachine : 4,
+            StateMachine::DataValue(_) | StateMachine::PostfixOperator(_) => states!(StateMachine : 7,
                 StateMachine::GroupEnd,
                 StateMachine::InfixOperator(InfixOperator::And),
                 StateMachine::InfixOperator(InfixOperator::Or),
+                StateMachine::PostfixOperator(PostfixOperator::Boost),
+                StateMachine::PostfixOperator(PostfixOperator::Fuzz),
                 StateMachine::EndOfInput,
+                StateMachine::Newline,
             ),
             StateMachine::Field => states!(StateMachine : 7,
                 StateMachine::Comparator(Comparator::Equal),
@@ -168,47 +217,72 @@ impl FSM for StateMachine {
                 StateMachine::Comparator(Comparator::GreaterThanOrEqual),
                 StateMachine::Comparator(Comparator::Contains),
             ),
-            StateMachine::Tag => states!(StateMachine :  4,
+            StateMachine::ColonField => states!(StateMachine : StateMachine::Comparator(Comparator::Colon)),
+            StateMachine::Tag | StateMachine::Wildcard => states!(StateMachine :  7,
                 StateMachine::InfixOperator(InfixOperator::And),
                 StateMachine::InfixOperator(InfixOperator::Or),
+                StateMachine::PostfixOperator(PostfixOperator::Boost),
+                StateMachine::PostfixOperator(PostfixOperator::Fuzz),
                 StateMachine::GroupEnd,
                 StateMachine::EndOfInput,
+                StateMachine::Newline,
             ),
             StateMachine::EndOfInput => states!(StateMachine : StateMachine::EndOfInput ),
         }
     }
 }
 
-impl FSMStateMatcher for StateMachine {
-    #[tracing::instrument]
-    fn matches(self, inp: &str) -> Option<u8> {
+impl StateMachine {
+    /// Like [FSMStateMatcher::matches] with the operators spelled as in the dialect
+    #[tracing::instrument(skip(dialect))]
+    pub fn matches_with(self, inp: &str, dialect: &Dialect) -> Option<usize> {
         match self {
             // Start never matches as it's immediately transitioned into a different state
             StateMachine::Start => None,
             StateMachine::GroupStart if inp.starts_with('(') => Some(1),
             StateMachine::GroupEnd if inp.starts_with(')') => Some(1),
-            StateMachine::InfixOperator(v) => v.matches(inp),
+            StateMachine::InfixOperator(v) => v.matches(inp, dialect),
             StateMachine::Comparator(v) => v.matches(inp),
-            StateMachine::PrefixOperator(v) => v.matches(inp),
-            StateMachine::DataValue(v) => v.matches(inp),
+            StateMachine::PrefixOperator(v) => v.matches(inp, dialect),
+            StateMachine::PostfixOperator(v) => v.matches(inp),
+            StateMachine::DataValue(v) => v.matches(inp, dialect),
             StateMachine::Field => {
-                let o = token_and_field::FieldLexem::new(inp).find_end();
+                let o = token_and_field::FieldLexem::new(inp, dialect).find_end();
                 trace!("field match got {} chars: {:?}", o, &inp[..o]);
                 if o < 2 {
                     None
                 } else {
-                    Some(o as u8)
+                    Some(o)
                 }
             }
+            StateMachine::ColonField => {
+                let o = inp.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))?;
+                let name = &inp[..o];
+                trace!("colon field candidate {:?}", name);
+                if o == 0 || !inp[o..].starts_with(':') {
+                    return None;
+                }
+                // whether it is a known field is up to the tokenizer's schema
+                Some(o)
+            }
             StateMachine::Tag => {
                 // TODO: Tags can contain stuff, this should try to peek
                 // ahead and stop when it finds control words
-                let o = token_and_field::TagLexem::new(inp).find_end();
+                let o = token_and_field::TagLexem::new(inp, dialect).find_end();
                 trace!("tag match got {} chars: {:?}", o, &inp[..o]);
-                if o < 2 {
+                if o < 2 || token_and_field::has_wildcard(&inp[..o]) {
                     None
                 } else {
-                    Some(o as u8)
+                    Some(o)
+                }
+            }
+            StateMachine::Wildcard => {
+                let o = token_and_field::TagLexem::new(inp, dialect).find_end();
+                trace!("wildcard match got {} chars: {:?}", o, &inp[..o]);
+                if token_and_field::has_wildcard(&inp[..o]) {
+                    Some(o)
+                } else {
+                    None
                 }
             }
             // EoI will only match if the input is zero-sized
@@ -216,23 +290,33 @@ impl FSMStateMatcher for StateMachine {
             _ => None,
         }
     }
+}
+
+impl FSMStateMatcher for StateMachine {
+    fn matches(self, inp: &str) -> Option<usize> {
+        self.matches_with(inp, &Dialect::default())
+    }
 
     #[tracing::instrument]
-    fn maximum_bound(self) -> Option<u8> {
+    fn maximum_bound(self) -> Option<usize> {
         match self {
             StateMachine::Start => Some(0),
             StateMachine::GroupStart => Some(1),
             StateMachine::GroupEnd => Some(1),
-            StateMachine::InfixOperator(v) => v.maximum_bound(),
+            // operators match their keywords exactly
+            StateMachine::InfixOperator(_) => None,
             StateMachine::Comparator(v) => v.maximum_bound(),
-            StateMachine::PrefixOperator(v) => v.maximum_bound(),
+            StateMachine::PrefixOperator(_) => None,
+            StateMachine::PostfixOperator(v) => v.maximum_bound(),
             StateMachine::DataValue(v) => v.maximum_bound(),
             // Maximum field name size is 64 characters (plus dot)
             // A field must be minimum 3 characters
             StateMachine::Field => Some(65),
+            StateMachine::ColonField => Some(64),
             // Maximum tag name size is 255 characters
             // A tag must be minimum 2 characters
-            StateMachine::Tag => Some(255),
+            StateMachine::Tag | StateMachine::Wildcard => Some(255),
+            StateMachine::Newline => None,
             StateMachine::EndOfInput => None,
         }
     }
@@ -2,12 +2,14 @@ use std::{iter::Peekable, str::CharIndices};
 
 use tracing::trace;
 
+use super::dialect::{is_word, is_word_char, Dialect};
+
 #[derive(Debug, Clone, Copy)]
 pub struct TagLexem<'a>(FieldOrTagLexem<'a>);
 
 impl<'a> TagLexem<'a> {
-    pub const fn new(data: &'a str) -> Self {
-        Self(FieldOrTagLexem::new(data))
+    pub const fn new(data: &'a str, dialect: &'a Dialect) -> Self {
+        Self(FieldOrTagLexem::new(data, dialect))
     }
 
     #[cfg(test)]
@@ -37,8 +39,8 @@ impl<'a> TagLexem<'a> {
 pub struct FieldLexem<'a>(FieldOrTagLexem<'a>);
 
 impl<'a> FieldLexem<'a> {
-    pub const fn new(data: &'a str) -> Self {
-        Self(FieldOrTagLexem::new(data))
+    pub const fn new(data: &'a str, dialect: &'a Dialect) -> Self {
+        Self(FieldOrTagLexem::new(data, dialect))
     }
 
     #[cfg(test)]
@@ -66,20 +68,39 @@ impl<'a> FieldLexem<'a> {
 
 /// Matches on tokens the terminate a field or tag
 /// and return the length of the lexem
+///
+/// The operators of the dialect terminate it, NOT symbols such as `-` only
+/// at its start, as does whitespace if the dialect implies AND between terms.
 #[derive(Debug, Clone, Copy)]
-pub struct FieldOrTagLexem<'a>(&'a str);
+pub struct FieldOrTagLexem<'a>(&'a str, &'a Dialect);
 
 const fn is_single_char_termination(c: char) -> bool {
     matches!(c, 
-        '(' | ')' | '*' | '?' | ',' | '"' | '~' | '^'
+        '(' | ')' | '"' | '~' | '^'
     )
 }
 
+/// Returns true if the lexeme contains a `*` or `?` that is not escaped
+/// with a backslash
+pub fn has_wildcard(lexeme: &str) -> bool {
+    let mut chars = lexeme.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
 type CList<'q> = Peekable<CharIndices<'q>>;
 
 impl<'a> FieldOrTagLexem<'a> {
-    pub const fn new(data: &'a str) -> Self {
-        Self(data)
+    pub const fn new(data: &'a str, dialect: &'a Dialect) -> Self {
+        Self(data, dialect)
     }
 
     #[cfg(test)]
@@ -92,29 +113,30 @@ impl<'a> FieldOrTagLexem<'a> {
 
     pub fn find_end(self) -> usize {
         let odata = self.0;
+        let dialect = self.1;
         let data_size = odata.bytes().count();
         let data: &mut CList = &mut odata.char_indices().peekable();
         let cret = |data_size| {
             trace!("found termination at {data_size}");
             let data = &odata[..data_size];
-            data.trim().len()
+            data.trim_end().len()
         };
         while let Some((pos, chr)) = data.next() {
             trace!("checking if char {chr:?} at {pos} terminates");
+            let rest = &odata[pos..];
+            // keywords such as AND only terminate at the start of a word
+            let word_start = !odata[..pos].ends_with(is_word_char);
             match chr {
                 // on escape, advance by one
-                '\\' if match_next_any(data, ['A', 'O', 'N', '&', '|']) => { data.next(); },
-                '\\' if pos == 0 && match_next_any(data, ['!', '-']) => { data.next(); },
+                '\\' if data.peek().is_some_and(|x| dialect.starts_keyword(x.1)) => { data.next(); },
                 '\\' if data.peek().map(|x| is_single_char_termination(x.1)).unwrap_or(false) => { data.next(); },
                 c if is_single_char_termination(c) => return cret(pos),
-                '.' => return cret(pos),
-                '-' | '!' if pos == 0 => return cret(pos),
-                'A' if match_n(data, ['N', 'D']) => return cret(pos),
-                'O' if match_1(data, 'R') => return cret(pos),
-                'N' if match_n(data, ['O', 'T'])=> return cret(pos),
-                '&' if match_1(data, '&') => return cret(pos),
-                '|' if match_1(data, '|') => return cret(pos),
-                // TODO: add "" quoting
+                '.' | '\n' | '\r' => return cret(pos),
+                c if c.is_whitespace() && dialect.implicit_and() => return cret(pos),
+                // a comment starts at a `#` after whitespace
+                '#' if odata[..pos].ends_with(char::is_whitespace) => return cret(pos),
+                _ if dialect.combinator_at(rest).is_some_and(|k| word_start || !is_word(k)) => return cret(pos),
+                _ if dialect.not_at(rest).is_some_and(|k| if is_word(k) { word_start } else { pos == 0 }) => return cret(pos),
                 _ => (),
             }
         }
@@ -123,53 +145,43 @@ impl<'a> FieldOrTagLexem<'a> {
     }
 }
 
-fn match_n<const N: usize>(d: &mut CList<'_>, m: [char; N]) -> bool {
-    let mut c = 0;
-    while let Some(peek) = d.peek() {
-        if m[c] != peek.1 {
-            return false;
-        }
-        c += 1;
-        if c >= N {
-            return true;
-        }
-        d.next();
-    }
-    true
-}
-
-fn match_next_any<const N: usize>(d: &mut CList<'_>, m: [char; N]) -> bool {
-    let c = match d.peek() {
-        Some(v) => *v,
-        None => return false,
-    };
-    for i in m {
-        if i == c.1 {
-            d.next();
-            return true;
-        }
-    }
-    false
-}
-
-#[tracing::instrument]
-fn match_1(d: &mut CList<'_>, m: char) -> bool {
-    d.peek().map(|x| x.1) == Some(m)
-}
-
 #[cfg(test)]
 mod test {
     use super::FieldOrTagLexem;
+    use crate::tokenizers::fsm::dialect::Dialect;
 
     #[test]
     #[tracing_test::traced_test]
     pub fn test_name_termination() {
-        assert_eq!("rose", FieldOrTagLexem::new(r"rose     ").find_end_str());
-        assert_eq!("rose", FieldOrTagLexem::new(r"rose (flower)").find_end_str());
-        assert_eq!("rose", FieldOrTagLexem::new(r"rose)").find_end_str());
-        assert_eq!(r"rose \(flower\)", FieldOrTagLexem::new(r"rose \(flower\)").find_end_str());
-        assert_eq!(r"", FieldOrTagLexem::new(r"-_-").find_end_str());
-        assert_eq!(r"\\-_-", FieldOrTagLexem::new(r"\\-_-").find_end_str());
-        assert_eq!("hello\\friend", FieldOrTagLexem::new("hello\\friend").find_end_str())
+        let d = &Dialect::default();
+        assert_eq!("rose", FieldOrTagLexem::new(r"rose     ", d).find_end_str());
+        assert_eq!("rose", FieldOrTagLexem::new(r"rose (flower)", d).find_end_str());
+        assert_eq!("rose", FieldOrTagLexem::new(r"rose)", d).find_end_str());
+        assert_eq!(r"rose \(flower\)", FieldOrTagLexem::new(r"rose \(flower\)", d).find_end_str());
+        assert_eq!(r"", FieldOrTagLexem::new(r"-_-", d).find_end_str());
+        assert_eq!(r"\\-_-", FieldOrTagLexem::new(r"\\-_-", d).find_end_str());
+        assert_eq!("hello\\friend", FieldOrTagLexem::new("hello\\friend", d).find_end_str());
+        assert_eq!("pony*", FieldOrTagLexem::new("pony* OR rose", d).find_end_str());
+        assert_eq!("?ose", FieldOrTagLexem::new("?ose)", d).find_end_str());
+        assert_eq!("rose", FieldOrTagLexem::new("rose\r\ntulip", d).find_end_str());
+        assert_eq!("c#", FieldOrTagLexem::new("c# # language", d).find_end_str());
+        assert_eq!("BRAND", FieldOrTagLexem::new("BRAND OR rose", d).find_end_str());
+        assert_eq!("rose", FieldOrTagLexem::new("rose,tulip", d).find_end_str());
+        let d = &Dialect::default().with_and(&["and", "&"]).with_or(&["or", "|"]).with_not(&["not"]);
+        assert_eq!("-_-", FieldOrTagLexem::new("-_- and rose", d).find_end_str());
+        assert_eq!("rose", FieldOrTagLexem::new("rose|tulip", d).find_end_str());
+        assert_eq!("rose,tulip", FieldOrTagLexem::new("rose,tulip", d).find_end_str());
+        assert_eq!("rose AND", FieldOrTagLexem::new("rose AND", d).find_end_str());
+        let d = &Dialect::default().with_implicit_and();
+        assert_eq!("red", FieldOrTagLexem::new("red rose", d).find_end_str());
+    }
+
+    #[test]
+    pub fn test_has_wildcard() {
+        assert!(super::has_wildcard("pony*"));
+        assert!(super::has_wildcard("r?se"));
+        assert!(!super::has_wildcard(r"what\?"));
+        assert!(!super::has_wildcard(r"\*star\*"));
+        assert!(!super::has_wildcard("rose"));
     }
 }
\ No newline at end of file
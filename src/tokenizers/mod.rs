@@ -1,9 +1,11 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
-use crate::{span::TokenSpan, tokens::Token, errors};
+use crate::{span::TokenSpan, tokens::Token, errors, schema::Schema};
 
 
-mod fsm;
+pub(crate) mod fsm;
+
+pub use fsm::dialect::Dialect;
 
 fn tokenspan_to_token(
     token_spans: &Vec<TokenSpan>,
@@ -12,11 +14,20 @@ fn tokenspan_to_token(
     token_spans.iter().map(|x| x.token()).collect()
 }
 
-pub trait ITokenizer: std::fmt::Debug {
-    fn new(input: Rc<str>) -> Box<dyn ITokenizer> where Self: Sized;
+pub trait ITokenizer: std::fmt::Debug + Send + Sync {
+    fn new(input: Arc<str>, schema: Arc<Schema>) -> Box<dyn ITokenizer> where Self: Sized;
+    /// The fields this tokenizer recognizes, passed on to the parser
+    fn schema(&self) -> Arc<Schema>;
+    /// Scans the input one token at a time, ending after the first error
+    fn spans(&mut self) -> Box<dyn Iterator<Item = errors::Result<TokenSpan>> + Send + '_>;
     fn token_spans(
         &mut self,
-    ) -> Result<Vec<TokenSpan>, crate::errors::Error>;
+    ) -> Result<Vec<TokenSpan>, crate::errors::Error> {
+        self.spans().collect()
+    }
+    /// Scans the whole input, covering what cannot be scanned with [Token::ERROR]
+    /// tokens and returning the errors alongside the best-effort token stream
+    fn recovering_token_spans(&mut self) -> (Vec<TokenSpan>, Vec<errors::Error>);
     fn tokens(&mut self) -> Result<Vec<Token>, crate::errors::Error> {
         let token_spans = self.token_spans()?;
         Ok(tokenspan_to_token(&token_spans))
@@ -25,7 +36,7 @@ pub trait ITokenizer: std::fmt::Debug {
 
 pub trait ITokenizerFactory: std::fmt::Debug {
     fn init() -> Box<dyn ITokenizerFactory> where Self: Sized;
-    fn new(&self, input: Rc<str>) -> Box<dyn ITokenizer>;
+    fn new(&self, input: Arc<str>, schema: Arc<Schema>) -> Box<dyn ITokenizer>;
 }
 
 pub struct Tokenizer {
@@ -49,10 +60,21 @@ pub fn tokenizers() -> Vec<String> {
 }
 
 pub fn tokenizer(name: &str, inp: &str) -> errors::Result<Box<dyn ITokenizer>> {
+    tokenizer_with_schema(name, inp, Arc::new(Schema::default()))
+}
+
+/// Like [tokenizer] but fields are recognized by the given schema
+pub fn tokenizer_with_schema(name: &str, inp: &str, schema: Arc<Schema>) -> errors::Result<Box<dyn ITokenizer>> {
     for tok in inventory::iter::<Tokenizer> {
         if tok.name == name {
-            return Ok((tok.imp)().new(Rc::from(inp)))
+            return Ok((tok.imp)().new(Arc::from(inp), schema))
         }
     }
     Err(errors::Error::UnknownTokenizer(name.to_string()))
 }
+
+/// Like [tokenizer_with_schema] for the `fsm` tokenizer with the operators
+/// spelled as in the dialect
+pub fn tokenizer_with_dialect(inp: &str, schema: Arc<Schema>, dialect: Dialect) -> Box<dyn ITokenizer> {
+    fsm::FSMFactory::with_dialect(dialect).new(Arc::from(inp), schema)
+}
@@ -12,9 +12,9 @@ pub enum Token {
     OR,
     /// "NOT", "!" or "-"
     NOT,
-    /// "^"
+    /// "^" followed by the boost factor
     BOOST,
-    /// "~"
+    /// "~" followed by the fuzziness
     FUZZ,
     /// "\""
     QUOTE,
@@ -34,12 +34,16 @@ pub enum Token {
     FIELD,
     /// Like field but a Tag explicitly not a field
     TAG,
+    /// A [Token::TAG] or `has:` value containing an unescaped "*" or "?"
+    WILDCARD,
     /// ".lte:", ".lt:", ".gte:", ".gt:", ".eq", ".neq"
     RANGE,
     /// new line LF or CRLF
     NEWLINE,
     /// End of Input
     EOI,
+    /// Input the tokenizer could not scan, only emitted when recovering from errors
+    ERROR,
 
     /// Term not surrounded with [Token::QUOTE]
     UNQUOTED_TERM,
@@ -80,9 +84,11 @@ impl Token {
             Token::RELATIVE_DATE => "Relative Date",
             Token::FIELD => "Field or Tag",
             Token::TAG => "Tag",
+            Token::WILDCARD => "Wildcard",
             Token::RANGE => "Range",
             Token::NEWLINE => "New Line",
             Token::EOI => "End of Input",
+            Token::ERROR => "Error",
             Token::UNQUOTED_TERM => "Unquoted Term",
             Token::QUOTED_TERM => "Quoted Term",
             Token::TERM => "Term",
@@ -1,8 +1,11 @@
-use crate::{errors, ast::Expr};
+use std::fmt::Write;
+
+use crate::{errors, ast::{Expr, SpanTree, Spanned}};
 
 use super::{ITransformerFactory, ITransformer};
 
 inventory::submit! { super::Transformer::new::<ASTDumpFactory>("ast") }
+inventory::submit! { super::Transformer::new::<SpannedASTDumpFactory>("spanned_ast") }
 
 pub struct ASTDump(Expr);
 
@@ -14,13 +17,13 @@ impl ITransformerFactory for ASTDumpFactory {
         Box::new(Self)
     }
 
-    fn new(&self, parser: Box<dyn crate::parsers::IParser>) -> crate::errors::Result<Box<dyn super::ITransformer>> {
-        Ok(ASTDump::new(parser)?)
+    fn new(&self, parser: Box<dyn crate::parsers::IParser>, clock: &dyn super::Clock, schema: std::sync::Arc<crate::schema::Schema>) -> crate::errors::Result<Box<dyn super::ITransformer>> {
+        Ok(ASTDump::new(parser, clock, schema)?)
     }
 }
 
 impl ITransformer for ASTDump {
-    fn new(mut parser: Box<dyn crate::parsers::IParser>) -> errors::Result<Box<dyn ITransformer>> where Self: Sized {
+    fn new(mut parser: Box<dyn crate::parsers::IParser>, _clock: &dyn super::Clock, _schema: std::sync::Arc<crate::schema::Schema>) -> errors::Result<Box<dyn ITransformer>> where Self: Sized {
         Ok(Box::new(Self(parser.produce_tree()?)))
     }
 
@@ -31,4 +34,58 @@ impl ITransformer for ASTDump {
         output.write_all(&[b'\n'])?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Dumps the AST one node per line, each with the part of the input it was parsed from
+pub struct SpannedASTDump(Spanned<Expr>);
+
+#[derive(Debug)]
+pub struct SpannedASTDumpFactory;
+
+impl ITransformerFactory for SpannedASTDumpFactory {
+    fn init() -> Box<dyn ITransformerFactory> where Self: Sized {
+        Box::new(Self)
+    }
+
+    fn new(&self, parser: Box<dyn crate::parsers::IParser>, clock: &dyn super::Clock, schema: std::sync::Arc<crate::schema::Schema>) -> crate::errors::Result<Box<dyn super::ITransformer>> {
+        SpannedASTDump::new(parser, clock, schema)
+    }
+}
+
+impl ITransformer for SpannedASTDump {
+    fn new(mut parser: Box<dyn crate::parsers::IParser>, _clock: &dyn super::Clock, _schema: std::sync::Arc<crate::schema::Schema>) -> errors::Result<Box<dyn ITransformer>> where Self: Sized {
+        Ok(Box::new(Self(parser.produce_spanned_tree()?)))
+    }
+
+    fn run(&mut self, mut output: Box<dyn std::io::Write>) -> crate::errors::Result<()> {
+        let mut out = String::new();
+        write_spanned(&mut out, &self.0.node, &self.0.spans, 0);
+        output.write_all(out.as_bytes())?;
+        Ok(())
+    }
+}
+
+fn write_spanned(out: &mut String, expr: &Expr, spans: &SpanTree, depth: usize) {
+    let indent = "    ".repeat(depth);
+    let operands: Vec<&Expr> = match expr {
+        Expr::Group(v) => {
+            let _ = writeln!(out, "{indent}Group {:?}", spans.span);
+            v.iter().collect()
+        }
+        Expr::Combine(op, v) => {
+            let _ = writeln!(out, "{indent}Combine({op:?}) {:?}", spans.span);
+            v.iter().collect()
+        }
+        Expr::Apply(op, e) => {
+            let _ = writeln!(out, "{indent}Apply({op:?}) {:?}", spans.span);
+            vec![e]
+        }
+        leaf => {
+            let _ = writeln!(out, "{indent}{leaf:?} {:?}", spans.span);
+            Vec::new()
+        }
+    };
+    for (e, s) in operands.into_iter().zip(&spans.children) {
+        write_spanned(out, e, s, depth + 1);
+    }
+}
@@ -1,9 +1,12 @@
-use std::collections::HashMap;
+use std::{net::IpAddr, sync::Arc};
 
-use crate::{ast::{Expr, ApplyOp, Comp, CombOp, Value}, errors};
+use ip_network::IpNetwork;
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
+
+use crate::{ast::{wildcard_prefix, DatePrecision, Expr, ApplyOp, Comp, CombOp, Value}, errors, schema::Schema};
 use elasticsearch_dsl::search::queries;
 
-use super::{ITransformerFactory, ITransformer};
+use super::{Clock, ITransformerFactory, ITransformer};
 
 inventory::submit! { super::Transformer::new::<ElasticFactory>("esq") }
 
@@ -15,16 +18,21 @@ impl ITransformerFactory for ElasticFactory {
         Box::new(Self)
     }
 
-    fn new(&self, parser: Box<dyn crate::parsers::IParser>) -> errors::Result<Box<dyn super::ITransformer>> {
-        Ok(ElasticTermProducer::new(parser)?)
+    fn new(&self, parser: Box<dyn crate::parsers::IParser>, clock: &dyn Clock, schema: Arc<Schema>) -> errors::Result<Box<dyn super::ITransformer>> {
+        Ok(ElasticTermProducer::new(parser, clock, schema)?)
     }
 }
 
-pub struct ElasticTermProducer { parser: Box<dyn crate::parsers::IParser> }
+pub struct ElasticTermProducer {
+    parser: Box<dyn crate::parsers::IParser>,
+    /// The moment relative dates are resolved against
+    now: OffsetDateTime,
+    schema: Arc<Schema>,
+}
 
 impl super::ITransformer for ElasticTermProducer {
-    fn new(parser: Box<dyn crate::parsers::IParser>) -> errors::Result<Box<dyn super::ITransformer>> where Self: Sized {
-        Ok(Box::new(Self{ parser }))
+    fn new(parser: Box<dyn crate::parsers::IParser>, clock: &dyn Clock, schema: Arc<Schema>) -> errors::Result<Box<dyn super::ITransformer>> where Self: Sized {
+        Ok(Box::new(Self{ parser, now: clock.now(), schema }))
     }
 
     fn run(&mut self, mut output: Box<dyn std::io::Write>) -> errors::Result<()> {
@@ -34,7 +42,7 @@ impl super::ITransformer for ElasticTermProducer {
             .stats("statistics")
             .from(0)
             .size(30)
-            .query(ElasticTerm::from(expr).0);
+            .query(ElasticTerm::new(expr, self.now, &self.schema)?.0);
         let ets = serde_json::to_string_pretty(&et)?;
         let mut ets = std::io::Cursor::new(ets.as_bytes());
         std::io::copy(&mut ets, &mut output)?;
@@ -43,67 +51,152 @@ impl super::ITransformer for ElasticTermProducer {
     }
 }
 
-pub fn hash<K: std::hash::Hash + Eq + PartialEq, V>(key: K, value: V) -> HashMap<K, V> {
-    let hm = HashMap::new();
-    extend_hash(hm, key, value)
+/// Formats a network for a term query, single addresses are written without netmask
+fn ip_term(net: IpNetwork) -> String {
+    match net {
+        IpNetwork::V4(v) if v.netmask() == 32 => v.network_address().to_string(),
+        IpNetwork::V6(v) if v.netmask() == 128 => v.network_address().to_string(),
+        v => v.to_string(),
+    }
+}
+
+/// Returns the first and last address contained in the network
+fn ip_bounds(net: IpNetwork) -> (IpAddr, IpAddr) {
+    match net {
+        IpNetwork::V4(v) => (v.network_address().into(), v.broadcast_address().into()),
+        IpNetwork::V6(v) => (v.network_address().into(), v.last_address().into()),
+    }
 }
 
-pub fn extend_hash<K: std::hash::Hash + Eq + PartialEq, V>(
-    mut hm: HashMap<K, V>,
-    key: K,
-    value: V,
-) -> HashMap<K, V> {
-    hm.insert(key, value);
-    hm
+/// Creates a range query over a date given as the start of it's span and its
+/// precision, `.lt:` is before the start and `.gt:` after the end while
+/// equality covers the entire span
+fn date_range(field: String, range: &str, start: OffsetDateTime, precision: DatePrecision) -> errors::Result<queries::Query> {
+    let out_of_range = |date: OffsetDateTime| errors::Error::DateOutOfRange(format!("date {}", date.date()));
+    let rfc3339 = |date: OffsetDateTime| date.format(&Rfc3339).map_err(|_| out_of_range(date));
+    let end = precision.end_of(start).ok_or_else(|| out_of_range(start))?;
+    let (lower, upper) = (rfc3339(start)?, rfc3339(end)?);
+    let query = queries::Query::range(field);
+    Ok(match range {
+        "lt" => query.lt(lower),
+        "lte" => query.lt(upper),
+        "gt" => query.gte(upper),
+        "gte" => query.gte(lower),
+        _ => query.gte(lower).lt(upper),
+    }.into())
+}
+
+/// Creates a range query over the span a relative date falls in for equality,
+/// the day, hour, minute or second by the largest of them its offset is a
+/// whole number of
+///
+/// `3 days ago` covers that entire day, weeks, months and years are counted in days.
+fn relative_range(field: String, now: OffsetDateTime, offset: Duration) -> errors::Result<queries::Query> {
+    let out_of_range = || errors::Error::DateOutOfRange(format!("relative date {offset}"));
+    let precision = [(Duration::DAY, DatePrecision::Day), (Duration::HOUR, DatePrecision::Hour), (Duration::MINUTE, DatePrecision::Minute)]
+        .into_iter()
+        .find(|(unit, _)| offset.whole_seconds() % unit.whole_seconds() == 0)
+        .map_or(DatePrecision::Second, |(_, precision)| precision);
+    let start = now.checked_add(offset).and_then(|x| precision.start_of(x)).ok_or_else(out_of_range)?;
+    date_range(field, "eq", start, precision)
+}
+
+/// Formats the moment a relative date refers to, the date must stay within four digit years
+fn resolve(now: OffsetDateTime, offset: Duration) -> errors::Result<String> {
+    now.checked_add(offset)
+        .and_then(|x| x.format(&Rfc3339).ok())
+        .ok_or_else(|| errors::Error::DateOutOfRange(format!("relative date {offset}")))
+}
+
+/// Sets the boost of a query, queries without one are wrapped to carry it
+fn boost(query: queries::Query, factor: f32) -> queries::Query {
+    use queries::Query;
+    match query {
+        Query::Bool(q) => q.boost(factor).into(),
+        Query::Term(q) => q.boost(factor).into(),
+        Query::Range(q) => q.boost(factor).into(),
+        Query::Fuzzy(q) => q.boost(factor).into(),
+        Query::MatchPhrase(q) => q.boost(factor).into(),
+        Query::Wildcard(q) => q.boost(factor).into(),
+        Query::Prefix(q) => q.boost(factor).into(),
+        q => Query::bool().must(q).boost(factor).into(),
+    }
+}
+
+/// Edit distance of a fuzzy match on `term`, rounded to whole edits. A fuzziness
+/// below 1 is the similarity of the old Lucene syntax and scales with the length
+/// of the term
+fn edit_distance(term: &str, fuzz: f64) -> u8 {
+    let distance = if fuzz < 1.0 {
+        (1.0 - fuzz) * term.chars().count() as f64
+    } else {
+        fuzz
+    };
+    // elasticsearch allows at most two edits
+    distance.round().clamp(0.0, 2.0) as u8
+}
+
+/// Elasticsearch stores integers as 64 bit
+fn integer(v: i128) -> errors::Result<i64> {
+    i64::try_from(v).map_err(|_| errors::Error::IntegerOutOfRange(v))
+}
+
+/// The name of a field to search, which cannot be empty
+fn field_name(field: String) -> errors::Result<String> {
+    if field.is_empty() {
+        return Err(errors::Error::UnsupportedExpr(Expr::Field(field)));
+    }
+    Ok(field)
 }
 
 macro_rules! qm_range {
-    (field_trim($field:expr)) => { {
-        let field: &str = $field.as_str();
-        assert!(field.len() > 1, "fields must have atleast 2 characters, including dot");
-        assert!(field.ends_with('.'), "fields must end with a dot");
-        field[..(field.len()-1)].to_string()
-    } };
-    ($inp:expr => cmp $q_type:ident $field:expr) => { {
-        let field = qm_range!(field_trim($field));
+    ($inp:expr => cmp $q_type:ident $field:expr, $now:expr) => { {
+        let field = field_name($field)?;
         let inp: Value = $inp;
         ElasticTerm(match inp {
-            Value::Integer(v) => {
-                assert!(v < (i64::MAX as i128), "integer value too large for range query");
-                assert!(v > (i64::MIN as i128), "integer value too large for range query");
-                queries::Query::range(field).$q_type(v as i64).into()
-            },
+            Value::Integer(v) => queries::Query::range(field).$q_type(integer(v)?).into(),
             Value::Float(v) => queries::Query::range(field).$q_type(v).into(),
             Value::Bool(v) => queries::Query::range(field).$q_type(v).into(),
-            Value::IP(v) => queries::Query::range(field).$q_type(v).into(),
-            Value::RelativeDate(v) => todo!("relative date match"),
-            Value::AbsoluteDate(v) => todo!("absolute date match"),
-            Value::Undefined => panic!("undefined value in range query"),
+            Value::IP(v) => {
+                // a range against a network is against it's first or last address
+                let (first, last) = ip_bounds(v);
+                let bound = match stringify!($q_type) {
+                    "gt" | "lte" => last,
+                    _ => first,
+                };
+                queries::Query::range(field).$q_type(bound).into()
+            },
+            Value::RelativeDate(v) => queries::Query::range(field).$q_type(resolve($now, v)?).into(),
+            Value::AbsoluteDate(v, p) => date_range(field, stringify!($q_type), v, p)?,
+            Value::String(v) => queries::Query::range(field).$q_type(v).into(),
+            Value::Undefined => queries::Query::match_none().into(),
         })
     } };
-    ($inp:expr => eq $field:expr) => { {
-        let field = qm_range!(field_trim($field));
+    ($inp:expr => eq $field:expr, $now:expr) => { {
+        let field = field_name($field)?;
         let right = $inp;
         ElasticTerm(match right {
-            Value::Integer(v) => queries::Query::term(field, v).into(),
+            Value::Integer(v) => queries::Query::term(field, integer(v)?).into(),
             Value::Float(v) => queries::Query::term(field, v.to_string()).into(),
             Value::Bool(v) => queries::Query::term(field, v.to_string()).into(),
-            Value::IP(v) => queries::Query::term(field, v.to_string()).into(),
-            Value::RelativeDate(v) => todo!("relative date match"),
-            Value::AbsoluteDate(v) => todo!("absolute date match"),
+            Value::IP(v) => queries::Query::term(field, ip_term(v)).into(),
+            Value::RelativeDate(v) => relative_range(field, $now, v)?,
+            Value::AbsoluteDate(v, p) => date_range(field, "eq", v, p)?,
+            Value::String(v) => queries::Query::term(field, v).into(),
             Value::Undefined => queries::Query::match_none().into(),
         })
     } };
-    ($inp:expr => neq $field:expr) => { {
-        let field = qm_range!(field_trim($field));
+    ($inp:expr => neq $field:expr, $now:expr) => { {
+        let field = field_name($field)?;
         let right = $inp;
         ElasticTerm(queries::Query::bool().must_not(match right {
-            Value::Integer(v) => queries::Query::Term(queries::Query::term(field, v)),
+            Value::Integer(v) => queries::Query::Term(queries::Query::term(field, integer(v)?)),
             Value::Float(v) => queries::Query::Term(queries::Query::term(field, v.to_string())),
             Value::Bool(v) => queries::Query::Term(queries::Query::term(field, v.to_string())),
-            Value::IP(v) => queries::Query::Term(queries::Query::term(field, v.to_string())),
-            Value::RelativeDate(v) => todo!("relative date match"),
-            Value::AbsoluteDate(v) => todo!("absolute date match"),
+            Value::IP(v) => queries::Query::Term(queries::Query::term(field, ip_term(v))),
+            Value::RelativeDate(v) => relative_range(field, $now, v)?,
+            Value::AbsoluteDate(v, p) => date_range(field, "eq", v, p)?,
+            Value::String(v) => queries::Query::Term(queries::Query::term(field, v)),
             Value::Undefined => queries::Query::MatchAll(queries::Query::match_all()),
         }).into())
     } };
@@ -113,32 +206,146 @@ macro_rules! qm_range {
 #[repr(transparent)]
 pub struct ElasticTerm(queries::Query);
 
-impl From<Expr> for ElasticTerm {
-    fn from(value: Expr) -> Self {
-        match value {
-            Expr::Apply(ApplyOp::Boost, _) => todo!(),
-            Expr::Apply(ApplyOp::Fuzz, _) => todo!(),
-            Expr::Apply(ApplyOp::Not, v) => ElasticTerm(queries::Query::bool().must_not({
-                let vq: ElasticTerm = (*v).into();
-                vq.0
-            }).into()),
-            Expr::Comparison(left, Comp::Equal, right) => qm_range!(right => eq left),
-            Expr::Comparison(left, Comp::NotEqual, right) => qm_range!(right => neq left),
-            Expr::Comparison(left, Comp::LessThan, right) => qm_range!(right => cmp lt left),
-            Expr::Comparison(left, Comp::LessThanOrEqual, right) => qm_range!(right => cmp lte left),
-            Expr::Comparison(left, Comp::GreaterThan, right) => qm_range!(right => cmp gt left),
-            Expr::Comparison(left, Comp::GreaterThanOrEqual, right) => qm_range!(right => cmp gte left),
-            Expr::Combine(CombOp::And, v) => ElasticTerm(queries::Query::bool().must(v.into_iter().map(|x| {
-                let y: ElasticTerm = x.into();
-                y.0
-            })).into()),
-            Expr::Combine(CombOp::Or, v) => ElasticTerm(queries::Query::bool().should(v.into_iter().map(|x| {
-                let y: ElasticTerm = x.into();
-                y.0
-            })).into()),
-            Expr::Group(_) => todo!("should not appear in optimal output"),
-            Expr::Tag(v) => ElasticTerm(queries::Query::term("tag", v.to_string()).into()),
-            v => todo!("not implemented: {v:?}"),
-        }
+impl ElasticTerm {
+    /// Converts the expression into a query, resolving relative dates against `now`
+    /// and searching tags in the tag field of the schema
+    pub fn new(value: Expr, now: OffsetDateTime, schema: &Schema) -> errors::Result<Self> {
+        let all = |v: Vec<Expr>| v.into_iter()
+            .map(|x| Ok(ElasticTerm::new(x, now, schema)?.0))
+            .collect::<errors::Result<Vec<_>>>();
+        Ok(match value {
+            Expr::Apply(ApplyOp::Boost(factor), v) => ElasticTerm(boost(ElasticTerm::new(*v, now, schema)?.0, factor as f32)),
+            Expr::Apply(ApplyOp::Fuzz(fuzz), v) => match *v {
+                Expr::Tag(v) => ElasticTerm(queries::Query::fuzzy(schema.tag_field(), &v).fuzziness(edit_distance(&v, fuzz)).into()),
+                Expr::Comparison(left, Comp::Equal, Value::Integer(v)) => ElasticTerm(
+                    queries::Query::range(field_name(left)?).gte(v as f64 - fuzz).lte(v as f64 + fuzz).into()
+                ),
+                Expr::Comparison(left, Comp::Equal, Value::Float(v)) => ElasticTerm(
+                    queries::Query::range(field_name(left)?).gte(v - fuzz).lte(v + fuzz).into()
+                ),
+                _ => return Err(errors::Error::UnsupportedFuzz),
+            },
+            Expr::Apply(ApplyOp::Not, v) => ElasticTerm(queries::Query::bool().must_not(ElasticTerm::new(*v, now, schema)?.0).into()),
+            Expr::Comparison(left, Comp::Equal, right) => qm_range!(right => eq left, now),
+            Expr::Comparison(left, Comp::NotEqual, right) => qm_range!(right => neq left, now),
+            Expr::Comparison(left, Comp::Contains, Value::String(right)) => ElasticTerm(
+                queries::Query::match_phrase(field_name(left)?, right).into()
+            ),
+            Expr::Comparison(left, Comp::Contains, right) => qm_range!(right => eq left, now),
+            Expr::Comparison(left, Comp::LessThan, right) => qm_range!(right => cmp lt left, now),
+            Expr::Comparison(left, Comp::LessThanOrEqual, right) => qm_range!(right => cmp lte left, now),
+            Expr::Comparison(left, Comp::GreaterThan, right) => qm_range!(right => cmp gt left, now),
+            Expr::Comparison(left, Comp::GreaterThanOrEqual, right) => qm_range!(right => cmp gte left, now),
+            Expr::Combine(CombOp::And, v) => ElasticTerm(queries::Query::bool().must(all(v)?).into()),
+            Expr::Combine(CombOp::Or, v) => ElasticTerm(queries::Query::bool().should(all(v)?).into()),
+            // groups left over by the parser are searched like a combination of their items
+            Expr::Group(mut v) if v.len() == 1 => ElasticTerm::new(v.remove(0), now, schema)?,
+            Expr::Group(v) => ElasticTerm(queries::Query::bool().must(all(v)?).into()),
+            Expr::Tag(v) => ElasticTerm(queries::Query::term(schema.tag_field(), v.to_string()).into()),
+            Expr::Tags(v) => ElasticTerm(queries::Query::terms(schema.tag_field(), v).into()),
+            Expr::Wildcard(field, pattern) => {
+                let field = match field {
+                    Some(field) => field_name(field)?,
+                    None => schema.tag_field().to_string(),
+                };
+                // patterns only ending in `*` are cheaper to search as prefix
+                ElasticTerm(match wildcard_prefix(&pattern) {
+                    Some(prefix) => queries::Query::prefix(field, prefix).into(),
+                    None => queries::Query::wildcard(field, pattern).into(),
+                })
+            }
+            Expr::Empty => ElasticTerm(queries::Query::match_none().into()),
+            v @ Expr::Field(_) => return Err(errors::Error::UnsupportedExpr(v)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::{macros::datetime, Duration};
+
+    use crate::ast::{ApplyOp, Comp, DatePrecision, Expr, Value};
+
+    use super::ElasticTerm;
+
+    fn query(expr: Expr) -> crate::errors::Result<serde_json::Value> {
+        Ok(serde_json::to_value(ElasticTerm::new(expr, datetime!(2024-01-10 12:00 UTC), &Default::default())?)?)
+    }
+
+    #[test]
+    pub fn test_relative_date() {
+        let expr = Expr::Comparison("created_at".to_string(), Comp::GreaterThanOrEqual, Value::RelativeDate(Duration::days(-3)));
+        assert_eq!(
+            serde_json::json!({"range": {"created_at": {"gte": "2024-01-07T12:00:00Z"}}}),
+            query(expr).unwrap()
+        );
+        // equality covers the day, hour, minute or second the date falls in
+        let expr = Expr::Comparison("created_at".to_string(), Comp::Equal, Value::RelativeDate(Duration::days(-3)));
+        assert_eq!(
+            serde_json::json!({"range": {"created_at": {"gte": "2024-01-07T00:00:00Z", "lt": "2024-01-08T00:00:00Z"}}}),
+            query(expr).unwrap()
+        );
+        let expr = Expr::Comparison("created_at".to_string(), Comp::NotEqual, Value::RelativeDate(Duration::minutes(-90)));
+        assert_eq!(
+            serde_json::json!({"bool": {"must_not": [{"range": {"created_at": {"gte": "2024-01-10T10:30:00Z", "lt": "2024-01-10T10:31:00Z"}}}]}}),
+            query(expr).unwrap()
+        );
+        let expr = Expr::Comparison("created_at".to_string(), Comp::Equal, Value::RelativeDate(Duration::days(365 * 8000)));
+        assert!(matches!(query(expr), Err(crate::errors::Error::DateOutOfRange(_))));
+        let expr = Expr::Comparison("created_at".to_string(), Comp::LessThan, Value::RelativeDate(Duration::days(365 * 8000)));
+        assert!(matches!(query(expr), Err(crate::errors::Error::DateOutOfRange(_))));
+    }
+
+    #[test]
+    pub fn test_absolute_date_out_of_range() {
+        let start = datetime!(2024-01-01 0:00 UTC).replace_year(-100).unwrap();
+        let expr = Expr::Comparison("created_at".to_string(), Comp::Equal, Value::AbsoluteDate(start, DatePrecision::Year));
+        assert!(matches!(query(expr), Err(crate::errors::Error::DateOutOfRange(_))));
+        // the year after 9999 ends the span
+        let expr = Expr::Comparison("created_at".to_string(), Comp::NotEqual, Value::AbsoluteDate(datetime!(9999-01-01 0:00 UTC), DatePrecision::Year));
+        assert!(matches!(query(expr), Err(crate::errors::Error::DateOutOfRange(_))));
+    }
+
+    #[test]
+    pub fn test_empty_field_name() {
+        let expr = Expr::Comparison(String::new(), Comp::LessThan, Value::Integer(1));
+        assert!(matches!(query(expr), Err(crate::errors::Error::UnsupportedExpr(Expr::Field(_)))));
+        let expr = Expr::Apply(ApplyOp::Fuzz(1.0), Box::new(Expr::Comparison(String::new(), Comp::Equal, Value::Integer(1))));
+        assert!(matches!(query(expr), Err(crate::errors::Error::UnsupportedExpr(Expr::Field(_)))));
+        assert!(matches!(query(Expr::Wildcard(Some(String::new()), "a*".to_string())), Err(crate::errors::Error::UnsupportedExpr(Expr::Field(_)))));
+    }
+
+    #[test]
+    pub fn test_boost_fuzz() {
+        let tag = |t: &str| Box::new(Expr::Tag(t.to_string()));
+        assert_eq!(
+            serde_json::json!({"term": {"tag": {"value": "rose", "boost": 2.0}}}),
+            query(Expr::Apply(ApplyOp::Boost(2.0), tag("rose"))).unwrap()
+        );
+        assert_eq!(
+            serde_json::json!({"fuzzy": {"tag": {"value": "tulip", "fuzziness": 1}}}),
+            query(Expr::Apply(ApplyOp::Fuzz(0.8), tag("tulip"))).unwrap()
+        );
+        assert_eq!(
+            serde_json::json!({"fuzzy": {"tag": {"value": "tulip", "fuzziness": 2}}}),
+            query(Expr::Apply(ApplyOp::Fuzz(5.0), tag("tulip"))).unwrap()
+        );
+        let expr = Expr::Comparison("score".to_string(), Comp::Equal, Value::Integer(10));
+        assert_eq!(
+            serde_json::json!({"range": {"score": {"gte": 8.0, "lte": 12.0}}}),
+            query(Expr::Apply(ApplyOp::Fuzz(2.0), Box::new(expr))).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn test_wildcard() {
+        assert_eq!(
+            serde_json::json!({"prefix": {"tag": {"value": "pony"}}}),
+            query(Expr::Wildcard(None, "pony*".to_string())).unwrap()
+        );
+        assert_eq!(
+            serde_json::json!({"wildcard": {"description": {"value": "big c?t*"}}}),
+            query(Expr::Wildcard(Some("description".to_string()), "big c?t*".to_string())).unwrap()
+        );
     }
 }
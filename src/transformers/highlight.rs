@@ -0,0 +1,264 @@
+use std::{ops::Range, sync::Arc};
+
+use crate::{errors, schema::Schema, span::TokenSpan, tokens::Token};
+
+use super::{Clock, ITransformer, ITransformerFactory};
+
+inventory::submit! { super::Transformer::new::<HighlightFactory>("highlight") }
+inventory::submit! { super::Transformer::new::<HighlightHtmlFactory>("highlight_html") }
+inventory::submit! { super::Transformer::new::<HighlightJsonFactory>("highlight_json") }
+
+/// What a part of the query is highlighted as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum Class {
+    #[serde(rename = "field")]
+    Field,
+    #[serde(rename = "comparator")]
+    Comparator,
+    #[serde(rename = "value-integer")]
+    Integer,
+    #[serde(rename = "value-float")]
+    Float,
+    #[serde(rename = "value-date")]
+    Date,
+    #[serde(rename = "value-ip")]
+    Ip,
+    #[serde(rename = "value-bool")]
+    Bool,
+    #[serde(rename = "value-string")]
+    String,
+    #[serde(rename = "tag")]
+    Tag,
+    #[serde(rename = "operator")]
+    Operator,
+    #[serde(rename = "paren")]
+    Paren,
+    #[serde(rename = "error")]
+    Error,
+}
+
+impl Class {
+    /// Classifies the token, `previous` tells values of a field apart from tags
+    fn of(token: &TokenSpan, previous: Option<Token>) -> Option<Self> {
+        let value = previous == Some(Token::RANGE);
+        Some(match token.token() {
+            Token::FIELD => Class::Field,
+            Token::RANGE => Class::Comparator,
+            Token::INTEGER => Class::Integer,
+            Token::FLOAT => Class::Float,
+            Token::ABSOLUTE_DATE | Token::RELATIVE_DATE => Class::Date,
+            Token::IP_CIDR => Class::Ip,
+            Token::BOOLEAN => Class::Bool,
+            Token::QUOTED_TERM | Token::UNQUOTED_TERM | Token::WILDCARD if value => Class::String,
+            Token::TAG | Token::QUOTED_TERM | Token::UNQUOTED_TERM | Token::WILDCARD => Class::Tag,
+            Token::AND | Token::OR | Token::NOT | Token::BOOST | Token::FUZZ => Class::Operator,
+            Token::LPAREN | Token::RPAREN => Class::Paren,
+            Token::ERROR => Class::Error,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Class::Field => "field",
+            Class::Comparator => "comparator",
+            Class::Integer => "value-integer",
+            Class::Float => "value-float",
+            Class::Date => "value-date",
+            Class::Ip => "value-ip",
+            Class::Bool => "value-bool",
+            Class::String => "value-string",
+            Class::Tag => "tag",
+            Class::Operator => "operator",
+            Class::Paren => "paren",
+            Class::Error => "error",
+        }
+    }
+
+    /// The SGR parameters of the colour in a terminal
+    fn ansi(self) -> &'static str {
+        match self {
+            Class::Field => "34",
+            Class::Comparator => "36",
+            Class::Integer | Class::Float | Class::Bool => "35",
+            Class::Date | Class::Ip => "33",
+            Class::String => "32",
+            Class::Tag => "1",
+            Class::Operator => "1;34",
+            Class::Paren => "2",
+            Class::Error => "4;31",
+        }
+    }
+}
+
+/// A classified part of the query
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Highlight {
+    pub range: Range<usize>,
+    pub class: Class,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ansi,
+    Html,
+    Json,
+}
+
+/// Highlights the query from the token stream, input that cannot be
+/// scanned is highlighted as an error instead of failing
+pub struct Highlighter {
+    input: Arc<str>,
+    highlights: Vec<Highlight>,
+    format: Format,
+}
+
+impl Highlighter {
+    fn with_format(mut parser: Box<dyn crate::parsers::IParser>, format: Format) -> Box<dyn ITransformer> {
+        let (tokens, _) = parser.produce_recovering_token_sequence();
+        let input = tokens.first().map(|root| Arc::from(root.str())).unwrap_or_else(|| Arc::from(""));
+        let mut previous = None;
+        let mut highlights = Vec::new();
+        // an implied AND is not part of the query
+        for token in tokens.into_iter().filter(|t| !t.range().is_empty()) {
+            if let Some(class) = Class::of(&token, previous) {
+                highlights.push(Highlight { range: token.range(), class });
+            }
+            previous = Some(token.token());
+        }
+        Box::new(Self { input, highlights, format })
+    }
+
+    /// The query with every highlight wrapped in the strings returned for its class
+    fn wrap(&self, open: impl Fn(Class) -> String, close: &str, escape: impl Fn(&str) -> String) -> String {
+        let mut out = String::new();
+        let mut position = 0;
+        for h in &self.highlights {
+            out += &escape(&self.input[position..h.range.start]);
+            out += &open(h.class);
+            out += &escape(&self.input[h.range.clone()]);
+            out += close;
+            position = h.range.end;
+        }
+        out += &escape(&self.input[position..]);
+        out
+    }
+}
+
+impl ITransformer for Highlighter {
+    fn new(parser: Box<dyn crate::parsers::IParser>, _clock: &dyn Clock, _schema: Arc<Schema>) -> errors::Result<Box<dyn ITransformer>> where Self: Sized {
+        Ok(Self::with_format(parser, Format::Ansi))
+    }
+
+    fn run(&mut self, mut output: Box<dyn std::io::Write>) -> errors::Result<()> {
+        let out = match self.format {
+            Format::Ansi => self.wrap(|c| format!("\x1b[{}m", c.ansi()), "\x1b[0m", str::to_string),
+            Format::Html => self.wrap(|c| format!("<span class=\"{}\">", c.name()), "</span>", escape_html),
+            Format::Json => serde_json::to_string(&self.highlights)?,
+        };
+        output.write_all(out.as_bytes())?;
+        output.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[derive(Debug)]
+pub struct HighlightFactory;
+
+impl ITransformerFactory for HighlightFactory {
+    fn init() -> Box<dyn ITransformerFactory> where Self: Sized {
+        Box::new(Self)
+    }
+
+    fn new(&self, parser: Box<dyn crate::parsers::IParser>, _clock: &dyn Clock, _schema: Arc<Schema>) -> errors::Result<Box<dyn ITransformer>> {
+        Ok(Highlighter::with_format(parser, Format::Ansi))
+    }
+}
+
+#[derive(Debug)]
+pub struct HighlightHtmlFactory;
+
+impl ITransformerFactory for HighlightHtmlFactory {
+    fn init() -> Box<dyn ITransformerFactory> where Self: Sized {
+        Box::new(Self)
+    }
+
+    fn new(&self, parser: Box<dyn crate::parsers::IParser>, _clock: &dyn Clock, _schema: Arc<Schema>) -> errors::Result<Box<dyn ITransformer>> {
+        Ok(Highlighter::with_format(parser, Format::Html))
+    }
+}
+
+#[derive(Debug)]
+pub struct HighlightJsonFactory;
+
+impl ITransformerFactory for HighlightJsonFactory {
+    fn init() -> Box<dyn ITransformerFactory> where Self: Sized {
+        Box::new(Self)
+    }
+
+    fn new(&self, parser: Box<dyn crate::parsers::IParser>, _clock: &dyn Clock, _schema: Arc<Schema>) -> errors::Result<Box<dyn ITransformer>> {
+        Ok(Highlighter::with_format(parser, Format::Json))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    /// Collects the output of a transformer
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn highlight(transformer: &str, query: &str) -> String {
+        let output = Output::default();
+        crate::tokenizer("fsm", query)
+            .and_then(|t| crate::parser("shift_reduce", t))
+            .and_then(|p| crate::transformer(transformer, p))
+            .and_then(|mut t| t.run(Box::new(output.clone())))
+            .unwrap();
+        let out = output.0.lock().unwrap().clone();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    pub fn test_highlight_json() {
+        assert_eq!(
+            concat!(
+                r#"[{"range":{"start":0,"end":1},"class":"paren"},{"range":{"start":1,"end":5},"class":"tag"},"#,
+                r#"{"range":{"start":6,"end":8},"class":"operator"},{"range":{"start":9,"end":15},"class":"field"},"#,
+                r#"{"range":{"start":15,"end":19},"class":"comparator"},{"range":{"start":19,"end":22},"class":"value-integer"},"#,
+                r#"{"range":{"start":22,"end":23},"class":"paren"}]"#, "\n"
+            ),
+            highlight("highlight_json", "(cute OR width.gte:100)")
+        );
+    }
+
+    #[test]
+    pub fn test_highlight_html() {
+        assert_eq!(
+            "<span class=\"tag\">&quot;a&lt;b&quot;</span><span class=\"operator\">,</span> <span class=\"field\">width.</span><span class=\"error\">gtee:10</span>\n",
+            highlight("highlight_html", "\"a<b\", width.gtee:10")
+        );
+    }
+
+    #[test]
+    pub fn test_highlight_ansi() {
+        assert_eq!(
+            "\x1b[1;34mNOT\x1b[0m \x1b[1mcute\x1b[0m # comment\n",
+            highlight("highlight", "NOT cute # comment")
+        );
+    }
+}
@@ -1,16 +1,43 @@
-use crate::{parsers::IParser, errors};
+use std::sync::Arc;
+
+use time::OffsetDateTime;
+
+use crate::{parsers::IParser, errors, schema::Schema};
 
 mod elastic;
 mod token_seq;
 mod ast;
+mod highlight;
+
+/// Source of the moment relative dates are resolved against
+pub trait Clock: std::fmt::Debug {
+    fn now(&self) -> OffsetDateTime;
+}
+
+/// Resolves against the current system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+/// A fixed point in time, for reproducible output
+impl Clock for OffsetDateTime {
+    fn now(&self) -> OffsetDateTime {
+        *self
+    }
+}
 
 pub trait ITransformerFactory: std::fmt::Debug {
     fn init() -> Box<dyn ITransformerFactory> where Self: Sized;
-    fn new(&self, parser: Box<dyn IParser>) -> errors::Result<Box<dyn ITransformer>>;
+    fn new(&self, parser: Box<dyn IParser>, clock: &dyn Clock, schema: Arc<Schema>) -> errors::Result<Box<dyn ITransformer>>;
 }
 
-pub trait ITransformer {
-    fn new(parser: Box<dyn IParser>) -> errors::Result<Box<dyn ITransformer>> where Self: Sized;
+pub trait ITransformer: Send + Sync {
+    fn new(parser: Box<dyn IParser>, clock: &dyn Clock, schema: Arc<Schema>) -> errors::Result<Box<dyn ITransformer>> where Self: Sized;
     fn run(&mut self, output: Box<dyn std::io::Write>) -> errors::Result<()>;
 }
 
@@ -35,9 +62,17 @@ pub fn transformers() -> Vec<String> {
 }
 
 pub fn transformer(name: &str, parser: Box<dyn IParser>) -> crate::errors::Result<Box<dyn ITransformer>> {
+    transformer_with_clock(name, parser, &SystemClock)
+}
+
+/// Like [transformer] but relative dates are resolved against the given clock
+///
+/// The transformer uses the schema the parser checked the fields against.
+pub fn transformer_with_clock(name: &str, parser: Box<dyn IParser>, clock: &dyn Clock) -> crate::errors::Result<Box<dyn ITransformer>> {
     for tra in inventory::iter::<Transformer> {
         if tra.name == name {
-            return Ok((tra.imp)().new(parser)?)
+            let schema = parser.schema();
+            return Ok((tra.imp)().new(parser, clock, schema)?)
         }
     }
     Err(errors::Error::UnknownTokenizer(name.to_string()))
@@ -14,13 +14,13 @@ impl ITransformerFactory for TokenSequenceFactory {
         Box::new(Self)
     }
 
-    fn new(&self, parser: Box<dyn crate::parsers::IParser>) -> crate::errors::Result<Box<dyn super::ITransformer>> {
-        Ok(TokenSequence::new(parser)?)
+    fn new(&self, parser: Box<dyn crate::parsers::IParser>, clock: &dyn super::Clock, schema: std::sync::Arc<crate::schema::Schema>) -> crate::errors::Result<Box<dyn super::ITransformer>> {
+        Ok(TokenSequence::new(parser, clock, schema)?)
     }
 }
 
 impl ITransformer for TokenSequence {
-    fn new(mut parser: Box<dyn crate::parsers::IParser>) -> errors::Result<Box<dyn ITransformer>> where Self: Sized {
+    fn new(mut parser: Box<dyn crate::parsers::IParser>, _clock: &dyn super::Clock, _schema: std::sync::Arc<crate::schema::Schema>) -> errors::Result<Box<dyn ITransformer>> where Self: Sized {
         Ok(Box::new(Self(parser.produce_token_sequence()?)))
     }
 